@@ -1,29 +1,138 @@
+use std::env::{temp_dir, var};
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::process::Command;
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use reqwest::{header, Error, Method};
+use reqwest::blocking::Response;
+use reqwest::{header, Method};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
+use crate::error::{ScribrError, ScribrResult};
+use crate::internal::{get_gh_token_file, read_file};
 use crate::model::{
     File, GhAccessResponse, GhDeviceCodeRequest, GhDeviceCodeResponse, GhFiles,
-    GhGistCreateRequest, GhGistResponse, GhPollRequest, SCRIBR_CONFIG_FILE_NAME,
+    GhGistCreateRequest, GhGistResponse, GhPollRequest, GhUserResponse, RemoteSettings,
+    SCRIBR_CONFIG_FILE_NAME,
 };
 
 const OAUTH_CLIENT_ID: &str = "2095923defc5784232a5";
-const GH_REQUEST_ERROR_LOG: &str = "Something went wrong with communicating with GitHub";
 const GH_DEFAULT_GIST_DESC: &str =
     "Gist for storing my scribr notes - https://gittoby.github.io/scribr/";
+/// Largest file the raw urls will serve, bigger files need a git clone of the gist.
+pub const GH_GIST_MAX_FILE_SIZE: usize = 10 * 1024 * 1024;
+const GH_TOKEN_ENV_VARS: [&str; 2] = ["SCRIBR_GH_TOKEN", "GITHUB_TOKEN"];
+const GH_DEFAULT_API_URL: &str = "https://api.github.com";
+const GH_DEFAULT_OAUTH_URL: &str = "https://github.com";
 
-fn make_web_request<B: Serialize, R: DeserializeOwned>(
+/// Where the access token for GitHub requests comes from.
+pub enum GhToken {
+    /// A token handed to scribr (env var or token file), used as is.
+    Provided { token: String, source: String },
+    /// The token saved by `scribr gh login`, refreshed with the device flow when missing or rejected.
+    Saved,
+}
+
+impl GhToken {
+    /// Picks the token in order of `--token-file`, env vars, the `token_file` setting and finally
+    /// the saved login.
+    pub fn resolve(
+        token_file: Option<&PathBuf>,
+        remote: Option<&RemoteSettings>,
+    ) -> ScribrResult<GhToken> {
+        if let Some(token_file) = token_file {
+            return GhToken::from_token_file(token_file);
+        }
+        for env_var in GH_TOKEN_ENV_VARS {
+            if let Some(token) = var(env_var).ok().filter(|token| !token.trim().is_empty()) {
+                return Ok(GhToken::Provided {
+                    token: token.trim().to_string(),
+                    source: format!("${}", env_var),
+                });
+            }
+        }
+        match remote.and_then(|remote| remote.token_file.as_ref()) {
+            Some(token_file) => GhToken::from_token_file(&PathBuf::from(token_file)),
+            None => Ok(GhToken::Saved),
+        }
+    }
+
+    fn from_token_file(token_file: &PathBuf) -> ScribrResult<GhToken> {
+        let token = load_gh_access_token(token_file)?.ok_or_else(|| {
+            ScribrError::Auth(format!(
+                "could not read a token from {}",
+                token_file.display()
+            ))
+        })?;
+        Ok(GhToken::Provided {
+            token,
+            source: token_file.display().to_string(),
+        })
+    }
+}
+
+/// The GitHub instance scribr talks to and how it authenticates there.
+pub struct GhRemote {
+    pub api_url: String,
+    pub oauth_url: String,
+    pub token: GhToken,
+}
+
+impl GhRemote {
+    pub fn new(
+        token_file: Option<&PathBuf>,
+        remote: Option<&RemoteSettings>,
+    ) -> ScribrResult<GhRemote> {
+        let api_url = remote.and_then(|remote| remote.api_url.as_deref());
+        let oauth_url = remote.and_then(|remote| remote.oauth_url.as_deref());
+        Ok(GhRemote {
+            api_url: api_url
+                .unwrap_or(GH_DEFAULT_API_URL)
+                .trim_end_matches('/')
+                .to_string(),
+            oauth_url: oauth_url
+                .unwrap_or(GH_DEFAULT_OAUTH_URL)
+                .trim_end_matches('/')
+                .to_string(),
+            token: GhToken::resolve(token_file, remote)?,
+        })
+    }
+
+    fn api(&self, path: &str) -> String {
+        format!("{}{}", self.api_url, path)
+    }
+
+    fn oauth(&self, path: &str) -> String {
+        format!("{}{}", self.oauth_url, path)
+    }
+
+    /// Gets the token to send, running the device flow if nothing is saved yet.
+    pub fn access_token(&self) -> ScribrResult<String> {
+        match &self.token {
+            GhToken::Provided { token, .. } => Ok(token.to_string()),
+            GhToken::Saved => match load_gh_access_token(&get_gh_token_file())? {
+                Some(token) => Ok(token),
+                None => gh_login(self),
+            },
+        }
+    }
+}
+
+fn send_web_request<B: Serialize>(
     method: Method,
     url: &str,
     token: Option<&str>,
     body: Option<&B>,
-) -> Result<R, Error> {
+) -> ScribrResult<Response> {
     let mut builder = reqwest::blocking::Client::builder()
-        .build()
-        .expect("Could not build the HTTP Request client")
+        .build()?
         .request(method, url)
         .header(header::ACCEPT, "application/json")
         .header(header::USER_AGENT, "scribr");
@@ -35,195 +144,611 @@ fn make_web_request<B: Serialize, R: DeserializeOwned>(
         builder = builder.json(body);
     }
 
-    let response = builder.send().expect(GH_REQUEST_ERROR_LOG);
+    Ok(builder.send()?.error_for_status()?)
+}
+
+fn make_web_request<B: Serialize, R: DeserializeOwned>(
+    method: Method,
+    url: &str,
+    token: Option<&str>,
+    body: Option<&B>,
+) -> ScribrResult<R> {
+    Ok(send_web_request(method, url, token, body)?.json::<R>()?)
+}
+
+/// Sends a request with the access token, logging in again once if GitHub rejects a saved token.
+fn send_authed_request<B: Serialize>(
+    gh_remote: &GhRemote,
+    method: Method,
+    url: &str,
+    body: Option<&B>,
+) -> ScribrResult<Response> {
+    let token = gh_remote.access_token()?;
+    match send_web_request(method.clone(), url, Some(&token), body) {
+        Err(ScribrError::Auth(_)) if matches!(gh_remote.token, GhToken::Saved) => {
+            println!("GitHub rejected the saved access token, logging in again...");
+            clear_gh_access_token(&get_gh_token_file());
+            let token = gh_login(gh_remote)?;
+            send_web_request(method, url, Some(&token), body)
+        }
+        Err(ScribrError::Auth(e)) => {
+            if let GhToken::Provided { source, .. } = &gh_remote.token {
+                return Err(ScribrError::Auth(format!(
+                    "the token from {} was rejected ({})",
+                    source, e
+                )));
+            }
+            Err(ScribrError::Auth(e))
+        }
+        result => result,
+    }
+}
+
+fn make_authed_request<B: Serialize, R: DeserializeOwned>(
+    gh_remote: &GhRemote,
+    method: Method,
+    url: &str,
+    body: Option<&B>,
+) -> ScribrResult<R> {
+    Ok(send_authed_request(gh_remote, method, url, body)?.json::<R>()?)
+}
+
+/// Pulls the `rel="next"` url out of a GitHub `Link` header.
+fn next_page_url(link_header: &str) -> Option<String> {
+    link_header.split(',').find_map(|link| {
+        let (url, params) = link.split_once(';')?;
+        if params
+            .split(';')
+            .any(|param| param.trim() == "rel=\"next\"")
+        {
+            Some(
+                url.trim()
+                    .trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_string(),
+            )
+        } else {
+            None
+        }
+    })
+}
 
-    match response.error_for_status() {
-        Ok(result) => result.json::<R>(),
-        Err(e) => Err(e),
+/// Gets every page of a list endpoint by following the `Link` headers.
+fn make_paged_authed_request<R: DeserializeOwned>(
+    gh_remote: &GhRemote,
+    url: &str,
+) -> ScribrResult<Vec<R>> {
+    let mut results = Vec::new();
+    let mut next_url = Some(format!("{}?per_page=100", url));
+    while let Some(url) = next_url {
+        let response = send_authed_request::<()>(gh_remote, Method::GET, &url, None)?;
+        next_url = response
+            .headers()
+            .get(header::LINK)
+            .and_then(|link| link.to_str().ok())
+            .and_then(next_page_url);
+        results.extend(response.json::<Vec<R>>()?);
     }
+    Ok(results)
 }
 
-fn send_access_code_request(device_code: &str) -> Option<GhAccessResponse> {
+fn send_access_code_request(gh_remote: &GhRemote, device_code: &str) -> Option<GhAccessResponse> {
     let body = GhPollRequest {
         client_id: OAUTH_CLIENT_ID.to_string(),
         device_code: device_code.to_string(),
         grant_type: "urn:ietf:params:oauth:grant-type:device_code".to_string(),
     };
-    let response: Result<GhAccessResponse, Error> = make_web_request(
+    make_web_request(
         Method::POST,
-        "https://github.com/login/oauth/access_token",
+        &gh_remote.oauth("/login/oauth/access_token"),
         None,
         Some(&body),
-    );
-    match response {
-        Ok(result) => Some(result),
-        Err(_) => None,
-    }
+    )
+    .ok()
 }
 
 // https://docs.github.com/en/apps/oauth-apps/building-oauth-apps/authorizing-oauth-apps#device-flow
-pub fn get_gh_access_token_oauth() -> String {
+pub fn get_gh_access_token_oauth(gh_remote: &GhRemote) -> ScribrResult<String> {
     let body = GhDeviceCodeRequest {
         client_id: OAUTH_CLIENT_ID.to_string(),
         scope: "gist".to_string(),
     };
 
-    let web_result: Result<GhDeviceCodeResponse, Error> = make_web_request(
+    let response: GhDeviceCodeResponse = make_web_request(
         Method::POST,
-        "https://github.com/login/device/code",
+        &gh_remote.oauth("/login/device/code"),
         None,
         Some(&body),
-    );
-
-    let response = match web_result {
-        Ok(res) => res,
-        Err(err) => panic!("{}: {}", GH_REQUEST_ERROR_LOG, err.to_string()),
-    };
+    )
+    .map_err(|e| ScribrError::Auth(format!("could not start the device flow ({})", e)))?;
 
     println!(
         "Log in to Github by entering your code, {}, at {}. I'll wait here!",
         response.user_code, response.verification_uri
     );
 
+    let expires_at = Instant::now() + Duration::from_secs(response.expires_in);
     let access_response: GhAccessResponse = loop {
-        match send_access_code_request(&response.device_code) {
+        match send_access_code_request(gh_remote, &response.device_code) {
             Some(response) => break response,
+            None if Instant::now() >= expires_at => {
+                return Err(ScribrError::Auth(
+                    "the login code expired before it was entered - try again".to_string(),
+                ));
+            }
             None => {
                 sleep(Duration::from_secs(response.interval));
             }
         }
     };
-    access_response.access_token
+    Ok(access_response.access_token)
+}
+
+pub fn load_gh_access_token(token_file: &PathBuf) -> ScribrResult<Option<String>> {
+    Ok(read_file(token_file)?
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty()))
 }
 
-pub fn gh_search_existing_scribr_gist(gh_access_token: &str) -> Option<GhGistResponse> {
-    let web_result = make_web_request::<(), Vec<GhGistResponse>>(
+pub fn save_gh_access_token(token_file: &PathBuf, token: &str) -> ScribrResult<()> {
+    let mut options = OpenOptions::new();
+    options.create(true).write(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let err_msg = format!("Could not save access token to {}", token_file.display());
+    let mut file = options
+        .open(token_file)
+        .map_err(|e| ScribrError::io(&err_msg, e))?;
+    // the mode above is only applied when the file is created
+    #[cfg(unix)]
+    fs::set_permissions(token_file, fs::Permissions::from_mode(0o600))
+        .map_err(|e| ScribrError::io(&err_msg, e))?;
+    file.write_all(token.as_bytes())
+        .map_err(|e| ScribrError::io(&err_msg, e))
+}
+
+pub fn clear_gh_access_token(token_file: &PathBuf) -> bool {
+    token_file.exists() && fs::remove_file(token_file).is_ok()
+}
+
+/// Runs the device flow and saves the resulting token for later runs.
+pub fn gh_login(gh_remote: &GhRemote) -> ScribrResult<String> {
+    let access_token = get_gh_access_token_oauth(gh_remote)?;
+    save_gh_access_token(&get_gh_token_file(), &access_token)?;
+    Ok(access_token)
+}
+
+pub fn gh_fetch_user(gh_remote: &GhRemote, gh_access_token: &str) -> ScribrResult<GhUserResponse> {
+    make_web_request::<(), GhUserResponse>(
         Method::GET,
-        "https://api.github.com/gists",
+        &gh_remote.api("/user"),
         Some(gh_access_token),
         None,
-    );
+    )
+}
 
-    let gists = match web_result {
-        Ok(res) => res,
-        Err(err) => panic!("{}: {}", GH_REQUEST_ERROR_LOG, err.to_string()),
-    };
+/// Lists every gist holding a scribr config file, most recently updated first.
+pub fn gh_list_scribr_gists(gh_remote: &GhRemote) -> ScribrResult<Vec<GhGistResponse>> {
+    let gists = make_paged_authed_request::<GhGistResponse>(gh_remote, &gh_remote.api("/gists"))?;
 
-    for gist in gists {
-        for gist_file_name in gist.files.keys() {
-            if gist_file_name == SCRIBR_CONFIG_FILE_NAME {
-                println!(
-                    "Using a gist I found with a {} file for note store: {}",
-                    SCRIBR_CONFIG_FILE_NAME, gist.html_url
-                );
-                return Some(gist);
-            }
-        }
+    Ok(gists
+        .into_iter()
+        .filter(|gist| gist.files.contains_key(SCRIBR_CONFIG_FILE_NAME))
+        .collect())
+}
+
+pub fn gh_search_existing_scribr_gist(
+    gh_remote: &GhRemote,
+) -> ScribrResult<Option<GhGistResponse>> {
+    let gists = gh_list_scribr_gists(gh_remote)?;
+    if gists.len() > 1 {
+        println!(
+            "Found {} gists with a {} file, see them with scribr gh list.",
+            gists.len(),
+            SCRIBR_CONFIG_FILE_NAME
+        );
     }
-    return None;
+
+    let gist = gists.into_iter().next();
+    if let Some(gist) = &gist {
+        println!(
+            "Using a gist I found with a {} file for note store: {}",
+            SCRIBR_CONFIG_FILE_NAME, gist.html_url
+        );
+    }
+    Ok(gist)
 }
 
 pub fn gh_fetch_existing_scribr_gist(
-    gh_access_token: &str,
+    gh_remote: &GhRemote,
     gist_id: &str,
-) -> Option<GhGistResponse> {
-    let url = format!("https://api.github.com/gists/{}", gist_id);
-    let web_result =
-        make_web_request::<(), GhGistResponse>(Method::GET, &*url, Some(gh_access_token), None);
-
-    match web_result {
-        Ok(res) => {
-            println!("Using provided gist for note store: {}", res.html_url);
-            Some(res)
-        }
-        Err(_) => None,
-    }
+) -> ScribrResult<GhGistResponse> {
+    let url = gh_remote.api(&format!("/gists/{}", gist_id));
+    let gist = make_authed_request::<(), GhGistResponse>(gh_remote, Method::GET, &url, None)?;
+    println!("Using provided gist for note store: {}", gist.html_url);
+    Ok(gist)
 }
 
-pub fn gh_create_scribr_gist(gh_access_token: &str, initial_files: GhFiles) -> GhGistResponse {
+pub fn gh_create_scribr_gist(
+    gh_remote: &GhRemote,
+    initial_files: GhFiles,
+) -> ScribrResult<GhGistResponse> {
     let body = GhGistCreateRequest {
         description: Some(String::from(GH_DEFAULT_GIST_DESC)),
         public: Some(false),
         files: initial_files,
     };
 
-    let web_result: Result<GhGistResponse, Error> = make_web_request(
+    let gist: GhGistResponse = make_authed_request(
+        gh_remote,
         Method::POST,
-        "https://api.github.com/gists",
-        Some(gh_access_token),
+        &gh_remote.api("/gists"),
         Some(&body),
-    );
-
-    match web_result {
-        Ok(res) => {
-            println!("Created a new gist for note store: {}", res.html_url);
-            res
-        }
-        Err(err) => panic!("{}: {}", GH_REQUEST_ERROR_LOG, err.to_string()),
-    }
+    )?;
+    println!("Created a new gist for note store: {}", gist.html_url);
+    Ok(gist)
 }
 
 pub fn gh_fetch_scribr_gist(
-    gh_access_token: &str,
+    gh_remote: &GhRemote,
     gist_id: &Option<&str>,
-) -> Option<GhGistResponse> {
+) -> ScribrResult<Option<GhGistResponse>> {
     match gist_id {
-        Some(gist_id) => gh_fetch_existing_scribr_gist(gh_access_token, gist_id),
-        None => gh_search_existing_scribr_gist(gh_access_token),
+        Some(gist_id) => gh_fetch_existing_scribr_gist(gh_remote, gist_id).map(Some),
+        None => gh_search_existing_scribr_gist(gh_remote),
     }
 }
 
-pub fn gh_pull_gist_files(gh_access_token: &str, gist_id: &str) -> GhFiles {
+/// Fetches the gist as it was at the given revision sha.
+pub fn gh_fetch_gist_revision(
+    gh_remote: &GhRemote,
+    gist_id: &str,
+    revision: &str,
+) -> ScribrResult<GhGistResponse> {
+    let url = gh_remote.api(&format!("/gists/{}/{}", gist_id, revision));
+    let gist = make_authed_request::<(), GhGistResponse>(gh_remote, Method::GET, &url, None)?;
+    println!("Using revision {} of gist {}", revision, gist.html_url);
+    Ok(gist)
+}
+
+/// Clones the gist with git to read files that are too big, or too many, for the api.
+fn git_clone_gist_files(
+    gist_info: &GhGistResponse,
+    revision: Option<&str>,
+) -> ScribrResult<GhFiles> {
+    let clone_dir = create_clone_dir(&gist_info.id)?;
+    let file_result = git_clone_gist_files_into(gist_info, revision, &clone_dir);
+    let _ = fs::remove_dir_all(&clone_dir);
+    file_result
+}
+
+/// Makes a new empty dir to clone a gist into. The name is unique to this run and the dir must
+/// not already exist, so nothing else on the machine can have put files there first.
+fn create_clone_dir(gist_id: &str) -> ScribrResult<PathBuf> {
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let clone_dir = temp_dir().join(format!(
+        "scribr-gist-{}-{}-{}",
+        gist_id,
+        process::id(),
+        started
+    ));
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    builder.mode(0o700);
+    builder.create(&clone_dir).map_err(|e| {
+        ScribrError::io(
+            format!("Could not make {} to clone the gist", clone_dir.display()),
+            e,
+        )
+    })?;
+    Ok(clone_dir)
+}
+
+fn git_clone_gist_files_into(
+    gist_info: &GhGistResponse,
+    revision: Option<&str>,
+    clone_dir: &Path,
+) -> ScribrResult<GhFiles> {
     let mut file_result = GhFiles::new();
-    let gist_info = gh_fetch_existing_scribr_gist(gh_access_token, gist_id)
-        .expect("Bad gist for backups - if the id right?");
 
-    for (filename, file_data) in &gist_info.files {
-        let response = reqwest::blocking::Client::builder()
-            .build()
-            .expect("Could not build the HTTP Request client")
-            .request(Method::GET, &file_data.raw_url)
-            .header(header::ACCEPT, "application/json")
-            .header(header::USER_AGENT, "scribr")
-            .bearer_auth(gh_access_token)
-            .send()
-            .expect(GH_REQUEST_ERROR_LOG);
-
-        match response.error_for_status() {
-            Ok(good_response) => good_response
-                .text()
-                .and_then(|content| {
-                    let file = File::from(content);
-                    file_result.insert(filename.to_owned(), file);
-                    Ok(())
-                })
-                .unwrap_or(()),
-            Err(e) => println!(
-                "Could not fetch data for {} from {} - {}",
-                &filename,
-                &file_data.raw_url,
-                e.to_string()
-            ),
+    println!(
+        "Cloning {} to read the full files...",
+        gist_info.git_pull_url
+    );
+    let mut clone = Command::new("git");
+    clone.arg("clone").arg("--quiet");
+    if revision.is_none() {
+        clone.arg("--depth=1");
+    }
+    let cloned = clone
+        .arg(&gist_info.git_pull_url)
+        .arg(clone_dir)
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false);
+    let checked_out = cloned
+        && match revision {
+            None => true,
+            Some(revision) => Command::new("git")
+                .arg("-C")
+                .arg(clone_dir)
+                .args(["checkout", "--quiet", revision])
+                .status()
+                .map(|status| status.success())
+                .unwrap_or(false),
         };
+    if !checked_out {
+        return Err(ScribrError::Network(format!(
+            "could not clone {} - is git installed?",
+            gist_info.git_pull_url
+        )));
     }
-    file_result
+
+    for filename in gist_info.files.keys() {
+        match read_file(&clone_dir.join(filename))? {
+            Some(content) => {
+                file_result.insert(filename.to_owned(), File::from(content));
+            }
+            None => println!("Could not find {} in the gist clone", filename),
+        }
+    }
+    Ok(file_result)
 }
 
-pub fn gh_push_gist_files(gh_access_token: &str, gist_id: &str, files: GhFiles) -> GhGistResponse {
+pub fn gh_pull_gist_files(
+    gh_remote: &GhRemote,
+    gist_id: &str,
+    revision: Option<&str>,
+) -> ScribrResult<GhFiles> {
+    let mut file_result = GhFiles::new();
+    let gist_info = match revision {
+        None => gh_fetch_existing_scribr_gist(gh_remote, gist_id)?,
+        Some(revision) => gh_fetch_gist_revision(gh_remote, gist_id, revision)?,
+    };
+    if gist_info.truncated {
+        println!("The gist has too many files to list through the GitHub api.");
+        return git_clone_gist_files(&gist_info, revision);
+    }
+
+    let mut needs_clone = false;
+    for (filename, file_data) in &gist_info.files {
+        if let (Some(content), false) = (&file_data.content, file_data.truncated) {
+            file_result.insert(filename.to_owned(), File::from(content.to_owned()));
+            continue;
+        }
+        if file_data.size as usize > GH_GIST_MAX_FILE_SIZE {
+            println!(
+                "{} is {} bytes, too big to download from the GitHub api.",
+                filename, file_data.size
+            );
+            needs_clone = true;
+            continue;
+        }
+
+        let content =
+            match send_authed_request::<()>(gh_remote, Method::GET, &file_data.raw_url, None)
+                .and_then(|response| Ok(response.text()?))
+            {
+                Ok(content) => content,
+                Err(e) => {
+                    println!("Could not download {} ({}).", filename, e);
+                    needs_clone = true;
+                    continue;
+                }
+            };
+        if content.len() as i64 == file_data.size {
+            file_result.insert(filename.to_owned(), File::from(content));
+        } else {
+            println!(
+                "Only got {} of {} bytes for {}.",
+                content.len(),
+                file_data.size,
+                filename
+            );
+            needs_clone = true;
+        }
+    }
+
+    if needs_clone {
+        for (filename, file) in git_clone_gist_files(&gist_info, revision)? {
+            file_result.entry(filename).or_insert(file);
+        }
+    }
+    Ok(file_result)
+}
+
+pub fn gh_push_gist_files(
+    gh_remote: &GhRemote,
+    gist_id: &str,
+    files: GhFiles,
+) -> ScribrResult<GhGistResponse> {
     let body = GhGistCreateRequest {
         description: Some(GH_DEFAULT_GIST_DESC.to_string()),
         public: None,
         files,
     };
-    let url = format!("https://api.github.com/gists/{}", gist_id);
-    let web_result: Result<GhGistResponse, Error> =
-        make_web_request(Method::PATCH, &*url, Some(gh_access_token), Some(&body));
-
-    match web_result {
-        Ok(res) => {
-            println!("Updated files on gist {}", res.html_url);
-            res
+    let url = gh_remote.api(&format!("/gists/{}", gist_id));
+    let gist: GhGistResponse = make_authed_request(gh_remote, Method::PATCH, &url, Some(&body))?;
+    println!("Updated files on gist {}", gist.html_url);
+    Ok(gist)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::*;
+
+    /// Serves canned bodies by request path on localhost, standing in for the gist api.
+    fn start_fake_gist_server(routes: HashMap<&str, String>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = format!("http://{}", listener.local_addr().unwrap());
+        let routes: HashMap<String, String> = routes
+            .into_iter()
+            .map(|(path, body)| (path.to_string(), body.replace("{server}", &server)))
+            .collect();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(&stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    header.clear();
+                }
+                let path = request_line.split(' ').nth(1).unwrap_or_default();
+                let response = match routes.get(path) {
+                    Some(body) => format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    ),
+                    None => {
+                        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_string()
+                    }
+                };
+                (&stream).write_all(response.as_bytes()).unwrap();
+            }
+        });
+        server
+    }
+
+    #[test]
+    fn test_gh_pull_gist_files_from_configured_api_url() {
+        let resources_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("resources")
+            .join("tests");
+        let gist = read_file(&resources_dir.join("gh_gist.json"))
+            .unwrap()
+            .unwrap();
+        let notes = "Wed, 1 Mar 2023 10:00:00 +0000 - a note from the remote\n";
+        let server = start_fake_gist_server(HashMap::from([
+            ("/api/v3/gists/tests-gist-id", gist.clone()),
+            ("/api/v3/gists/tests-gist-id/c0ffee", gist),
+            ("/raw/notes.txt", notes.to_string()),
+        ]));
+        let gh_remote = fake_gist_remote(server);
+
+        let files = gh_pull_gist_files(&gh_remote, "tests-gist-id", None).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files["notes.txt"].content, notes);
+
+        let files = gh_pull_gist_files(&gh_remote, "tests-gist-id", Some("c0ffee")).unwrap();
+        assert_eq!(files["notes.txt"].content, notes);
+    }
+
+    /// Makes a git repo holding `notes` as notes.txt, for the gist clone fallback to read.
+    fn create_fake_gist_repo(name: &str, notes: &str) -> PathBuf {
+        let repo_dir = temp_dir().join(format!("scribr-test-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&repo_dir);
+        fs::create_dir_all(&repo_dir).unwrap();
+        fs::write(repo_dir.join("notes.txt"), notes).unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(&repo_dir)
+                .args([
+                    "-c",
+                    "user.name=scribr",
+                    "-c",
+                    "user.email=scribr@localhost",
+                ])
+                .args(args)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        git(&["init", "--quiet"]);
+        git(&["add", "notes.txt"]);
+        git(&["commit", "--quiet", "-m", "notes"]);
+        repo_dir
+    }
+
+    fn fake_gist_remote(server: String) -> GhRemote {
+        GhRemote {
+            api_url: format!("{}/api/v3", server),
+            oauth_url: server,
+            token: GhToken::Provided {
+                token: "test-token".to_string(),
+                source: "tests".to_string(),
+            },
         }
-        Err(err) => panic!("{}: {}", GH_REQUEST_ERROR_LOG, err.to_string()),
+    }
+
+    #[test]
+    fn test_gh_pull_gist_files_falls_back_to_git_clone() {
+        let resources_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("resources")
+            .join("tests");
+        let notes = "Wed, 1 Mar 2023 10:00:00 +0000 - a note only in the clone\n";
+        let repo_dir = create_fake_gist_repo("clone-fallback", notes);
+        let gist = read_file(&resources_dir.join("gh_gist.json"))
+            .unwrap()
+            .unwrap()
+            .replace(
+                "{server}/tests-gist-id.git",
+                &format!("file://{}", repo_dir.display()),
+            );
+        let truncated_gist = gist.replace("\"truncated\": false", "\"truncated\": true");
+        let oversized_gist = gist.replace(
+            "\"size\": 56",
+            &format!("\"size\": {}", GH_GIST_MAX_FILE_SIZE + 1),
+        );
+        let server = start_fake_gist_server(HashMap::from([
+            ("/api/v3/gists/truncated-gist-id", truncated_gist),
+            ("/api/v3/gists/oversized-gist-id", oversized_gist),
+            ("/api/v3/gists/unreachable-gist-id", gist),
+        ]));
+        let gh_remote = fake_gist_remote(server);
+
+        let files = gh_pull_gist_files(&gh_remote, "truncated-gist-id", None).unwrap();
+        assert_eq!(files["notes.txt"].content, notes);
+
+        let files = gh_pull_gist_files(&gh_remote, "oversized-gist-id", None).unwrap();
+        assert_eq!(files["notes.txt"].content, notes);
+
+        // the raw url isn't served, so the download fails
+        let files = gh_pull_gist_files(&gh_remote, "unreachable-gist-id", None).unwrap();
+        assert_eq!(files["notes.txt"].content, notes);
+
+        fs::remove_dir_all(&repo_dir).unwrap();
+    }
+
+    #[test]
+    fn test_next_page_url() {
+        let link = "<https://api.github.com/gists?per_page=100&page=2>; rel=\"next\", \
+                    <https://api.github.com/gists?per_page=100&page=5>; rel=\"last\"";
+        assert_eq!(
+            next_page_url(link),
+            Some("https://api.github.com/gists?per_page=100&page=2".to_string())
+        );
+        let last_page = "<https://api.github.com/gists?per_page=100&page=1>; rel=\"first\"";
+        assert_eq!(next_page_url(last_page), None);
+    }
+
+    #[test]
+    fn test_gh_access_token_round_trip() {
+        let token_file = temp_dir().join("scribr_test_gh_token");
+        save_gh_access_token(&token_file, "gho_test_token").unwrap();
+        assert_eq!(
+            load_gh_access_token(&token_file).unwrap(),
+            Some("gho_test_token".to_string())
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            fs::metadata(&token_file).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        assert!(clear_gh_access_token(&token_file));
+        assert_eq!(load_gh_access_token(&token_file).unwrap(), None);
+        assert!(!clear_gh_access_token(&token_file));
     }
 }
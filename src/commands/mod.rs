@@ -1,81 +1,482 @@
 use std::cmp::min;
+use std::collections::BTreeSet;
+use std::env::temp_dir;
 use std::fs;
 use std::fs::{create_dir_all, write, File, OpenOptions};
-use std::io::BufReader;
-use std::io::Write;
-use std::path::PathBuf;
+use std::io::{BufRead, BufReader, IsTerminal, Read, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, process};
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate};
 
 use fuzzy_matcher::skim::SkimMatcherV2;
 use fuzzy_matcher::FuzzyMatcher;
-use rev_lines::RevLines;
 
 use crate::commands::github::{
-    get_gh_access_token_oauth, gh_create_scribr_gist, gh_fetch_scribr_gist, gh_pull_gist_files,
-    gh_push_gist_files,
+    clear_gh_access_token, gh_create_scribr_gist, gh_fetch_existing_scribr_gist,
+    gh_fetch_scribr_gist, gh_fetch_user, gh_list_scribr_gists, gh_login, gh_pull_gist_files,
+    gh_push_gist_files, load_gh_access_token, GhRemote, GhToken, GH_GIST_MAX_FILE_SIZE,
+};
+use crate::error::{ScribrError, ScribrResult};
+use crate::internal::{
+    check_notebook, diff_notes, diff_settings, fetch_user_confirm, find_note, format_duration,
+    get_default_init_files, get_gh_token_file, get_notebook_file_name, get_notebook_name,
+    get_notebook_paths, get_scribr_config_file, get_scribr_home_dir, get_settings_from_disk,
+    merge_notes, notebook_lines, pair_timers, parse_due, read_file, replace_note, resolve_link,
+    save_settings_to_disk, strip_editor_header, total_time_by_tag, TimerSpan, BACKUP_EXTENSION,
+    EDITOR_COMMENT_PREFIX, EDITOR_SCISSORS_LINE, NOTEBOOK_EXTENSION, REJECTS_EXTENSION,
+};
+use crate::journal;
+use crate::magic::MagicEngine;
+use crate::model::{
+    normalise_mention, File as GhFile, GhFiles, Note, RemoteSettings, Settings, NOTE_DUE_FORMAT,
+    SCRIBR_CONFIG_FILE_NAME,
 };
-use crate::internal::{get_default_init_files, get_scribr_home_dir, read_file};
-use crate::model::{File as GhFile, GhFiles, Note, Settings, SCRIBR_CONFIG_FILE_NAME};
 
 mod github;
 
-fn get_notes_file(notes_file: PathBuf) -> File {
-    let file = match OpenOptions::new()
+fn get_notes_file(notes_file: PathBuf) -> ScribrResult<File> {
+    OpenOptions::new()
         .create(true)
         .append(true)
         .read(true)
         .open(&notes_file)
-    {
-        Ok(file) => file,
-        Err(_) => panic!("Error opening notes file"),
-    };
-    file
+        .map_err(|e| {
+            ScribrError::io(
+                format!("Error opening notes file {}", notes_file.display()),
+                e,
+            )
+        })
+}
+
+/// Editor used to write a note when neither `$VISUAL` nor `$EDITOR` are set.
+const DEFAULT_EDITOR: &str = "vi";
+
+/// Timesheet row for time on notes without a tag.
+const TIMESHEET_UNTAGGED: &str = "(untagged)";
+
+/// Console indent for the second and later lines of a multi-line note.
+const NOTE_INDENT: &str = "    ";
+
+/// Reads a note piped in on stdin, dropping the trailing newline most tools add.
+fn read_note_from_stdin() -> ScribrResult<String> {
+    let mut note = String::new();
+    std::io::stdin()
+        .read_to_string(&mut note)
+        .map_err(|e| ScribrError::io("Could not read the note from stdin", e))?;
+    Ok(note.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Makes a new temp file holding `content` for the editor. Only the user can read it, and the name
+/// is unique to this run and must not already exist, so nothing can be waiting there for it.
+fn create_editor_file(content: &str) -> ScribrResult<PathBuf> {
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let note_path = temp_dir().join(format!("scribr-note-{}-{}.txt", process::id(), started));
+    let err_msg = format!("Could not write {}", note_path.display());
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options
+        .open(&note_path)
+        .map_err(|e| ScribrError::io(&err_msg, e))?;
+    file.write_all(content.as_bytes())
+        .map_err(|e| ScribrError::io(&err_msg, e))?;
+    Ok(note_path)
+}
+
+/// Opens `$VISUAL` or `$EDITOR` on a temp file to write a note, starting from `note_value`, and
+/// returns what's left once the comment header is stripped.
+fn read_note_from_editor(
+    settings: &Settings,
+    title: &str,
+    note_value: &str,
+) -> ScribrResult<String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| DEFAULT_EDITOR.to_string());
+    let mut editor_args = editor.split_whitespace();
+    let editor_cmd = editor_args
+        .next()
+        .ok_or_else(|| ScribrError::Config("$EDITOR is set but empty".to_string()))?;
+
+    let content = format!(
+        "{0} {1}\n{0} Notebook: {2}\n{0} Write the note below this line, save an empty note to cancel.\n{3}\n{4}\n",
+        EDITOR_COMMENT_PREFIX,
+        title,
+        settings.default_notebook,
+        EDITOR_SCISSORS_LINE,
+        note_value
+    );
+    let note_path = create_editor_file(&content)?;
+
+    let status = Command::new(editor_cmd)
+        .args(editor_args)
+        .arg(&note_path)
+        .status()
+        .map_err(|e| ScribrError::io(format!("Could not run editor {}", editor), e));
+    let content = read_file(&note_path);
+    let _ = fs::remove_file(&note_path);
+
+    if !status?.success() {
+        return Err(ScribrError::Config(format!(
+            "editor {} exited with an error, the note was not saved",
+            editor
+        )));
+    }
+    Ok(strip_editor_header(&content?.unwrap_or_default()))
 }
 
-pub fn take_note(settings: Settings, note: &str, echo: &bool) {
+pub fn take_note(
+    settings: Settings,
+    note: &Option<String>,
+    echo: &bool,
+    interactive: &bool,
+    todo: &bool,
+    due: &Option<String>,
+) -> ScribrResult<()> {
+    let mut full_note = if *interactive {
+        match Note::from_prompt()? {
+            Some(note) => note,
+            None => {
+                println!("\nNothing to note, no note was taken.");
+                return Ok(());
+            }
+        }
+    } else {
+        let note = match note.as_deref() {
+            None if std::io::stdin().is_terminal() => {
+                let title = format!("Taking a note at {}", Local::now().to_rfc2822());
+                read_note_from_editor(&settings, &title, "")?
+            }
+            None | Some("-") => read_note_from_stdin()?,
+            Some(note) => note.to_string(),
+        };
+        if note.trim().is_empty() {
+            println!("Nothing to note, the note was empty.");
+            return Ok(());
+        }
+        Note::new(&note)
+    };
+    if *todo {
+        full_note.mark_todo();
+    }
+    if let Some(due) = due {
+        full_note.set_due(parse_due(due, Local::now().date_naive())?);
+    }
+    MagicEngine::new(&settings).on_take(&mut full_note)?;
+
     let verbosity = settings.verbosity;
     if verbosity > 0 {
-        println!("✏️✏️✏️ Taking note {}", note);
+        println!("✏️✏️✏️ Taking note {}", full_note.note_value);
     }
 
-    let mut file = get_notes_file(settings.get_default_notebook_path());
-
-    let full_note = Note::new(note);
     if *echo {
-        println!("{}", full_note)
+        println!("{}", full_note);
+        return Ok(());
+    }
+    let notes_path = settings.get_default_notebook_path();
+    journal::record(&format!("take {}", full_note.id), &[&notes_path])?;
+    append_notes(&notes_path, &[full_note])
+}
+
+fn append_notes(notes_path: &Path, notes: &[Note]) -> ScribrResult<()> {
+    let mut file = get_notes_file(notes_path.to_path_buf())?;
+    for note in notes {
+        writeln!(file, "{}", note).map_err(|e| {
+            ScribrError::io(format!("Could not write to {}", notes_path.display()), e)
+        })?;
+    }
+    Ok(())
+}
+
+/// The timer that was started last in a notebook, if it hasn't been stopped.
+fn get_running_timer(notes: &[Note]) -> Option<TimerSpan> {
+    pair_timers(notes)
+        .into_iter()
+        .filter(|span| span.stopped_at.is_none())
+        .max_by_key(|span| span.start.timestamp)
+}
+
+/// Stop note for a running timer, with how long it ran.
+fn stop_timer_note(span: &TimerSpan) -> Note {
+    Note::new_timer_stop(&span.start, &format_duration(span.duration(Local::now())))
+}
+
+pub fn start_timer(settings: Settings, note: &str) -> ScribrResult<()> {
+    let notes_path = settings.get_default_notebook_path();
+    let (_, notes) = read_notebook(&notes_path)?;
+
+    let mut new_notes = Vec::new();
+    if let Some(running) = get_running_timer(&notes) {
+        let stop = stop_timer_note(&running);
+        println!(
+            "⏹️ Stopped {} after {}",
+            running.start.note_value,
+            format_duration(running.duration(stop.timestamp))
+        );
+        new_notes.push(stop);
+    }
+    let start = Note::new_timer_start(note);
+    println!("▶️ Started {} [{}]", start.note_value, start.id);
+    journal::record(&format!("start {}", start.id), &[&notes_path])?;
+    new_notes.push(start);
+    append_notes(&notes_path, &new_notes)
+}
+
+pub fn stop_timer(settings: Settings) -> ScribrResult<()> {
+    let notes_path = settings.get_default_notebook_path();
+    let (_, notes) = read_notebook(&notes_path)?;
+    let running = get_running_timer(&notes).ok_or_else(|| {
+        ScribrError::NoteNotFound("no timer is running, start one with scribr start".to_string())
+    })?;
+
+    let stop = stop_timer_note(&running);
+    journal::record(&format!("stop {}", running.start.id), &[&notes_path])?;
+    append_notes(&notes_path, std::slice::from_ref(&stop))?;
+    println!(
+        "⏹️ Stopped {} after {}",
+        running.start.note_value,
+        format_duration(running.duration(stop.timestamp))
+    );
+    Ok(())
+}
+
+pub fn show_timesheet(settings: Settings, week: &bool, csv: &bool) -> ScribrResult<()> {
+    let now = Local::now();
+    let since = if *week {
+        let today = now.date_naive();
+        let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+        Some(monday)
+    } else {
+        None
+    };
+
+    let mut spans = Vec::new();
+    for notebook_path in get_notebook_paths(&settings)? {
+        let (_, notes) = read_notebook(&notebook_path)?;
+        spans.extend(
+            pair_timers(&notes).into_iter().filter(|span| {
+                since.is_none_or(|since| span.start.timestamp.date_naive() >= since)
+            }),
+        );
+    }
+    let totals = total_time_by_tag(&spans, now, TIMESHEET_UNTAGGED);
+    let overall = spans
+        .iter()
+        .fold(Duration::zero(), |total, span| total + span.duration(now));
+
+    if *csv {
+        println!("tag,minutes,hours");
+        for (tag, total) in &totals {
+            println!(
+                "{},{},{:.2}",
+                tag,
+                total.num_minutes(),
+                total.num_minutes() as f64 / 60.0
+            );
+        }
+        return Ok(());
+    }
+
+    if let Some(since) = since {
+        println!("Week starting {}", since.format("%a %d %b %Y"));
+    }
+    if totals.is_empty() {
+        println!("No time tracked, start a timer with scribr start.");
+        return Ok(());
+    }
+    let width = totals
+        .iter()
+        .map(|(tag, _)| tag.len() + 1)
+        .max()
+        .unwrap_or(0)
+        .max("Total".len());
+    for (tag, total) in &totals {
+        let tag = if tag == TIMESHEET_UNTAGGED {
+            tag.to_string()
+        } else {
+            format!("#{}", tag)
+        };
+        println!(
+            "{:<width$}  {:>8}",
+            tag,
+            format_duration(*total),
+            width = width
+        );
+    }
+    println!(
+        "{:<width$}  {:>8}",
+        "Total",
+        format_duration(overall),
+        width = width
+    );
+    if spans.iter().any(|span| span.stopped_at.is_none()) {
+        println!("Includes a timer that is still running.");
+    }
+    Ok(())
+}
+
+/// A line read from a notebook, or the raw bytes of one that isn't UTF-8.
+type NotebookLine = Result<String, Vec<u8>>;
+
+/// Reads the notebook newest line first, along with each line's number in the file.
+fn read_lines_rev(
+    notes_path: PathBuf,
+) -> ScribrResult<impl Iterator<Item = (usize, NotebookLine)>> {
+    let err_msg = format!("Could not read notes file {}", notes_path.display());
+    let lines = BufReader::new(get_notes_file(notes_path)?)
+        .split(b'\n')
+        .map(|raw_line| {
+            let raw_line = raw_line?;
+            Ok(String::from_utf8(raw_line)
+                .map(|line| line.trim_end_matches('\r').to_string())
+                .map_err(|e| e.into_bytes()))
+        })
+        .collect::<Result<Vec<NotebookLine>, std::io::Error>>()
+        .map_err(|e| ScribrError::io(&err_msg, e))?;
+    Ok(lines
+        .into_iter()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line))
+        .rev())
+}
+
+/// Whether a notebook line is blank, which isn't a note but isn't malformed either.
+fn is_blank_line(line: &NotebookLine) -> bool {
+    line.as_ref().is_ok_and(|line| line.trim().is_empty())
+}
+
+/// Parses a notebook line, noting the line number of anything malformed unless `strict` is set.
+fn parse_note_line(
+    line_number: usize,
+    line: &NotebookLine,
+    strict: &bool,
+    skipped: &mut Vec<usize>,
+) -> ScribrResult<Option<Note>> {
+    if is_blank_line(line) {
+        return Ok(None);
+    }
+    let parsed = match line {
+        Ok(line) => Note::new_from_line(line),
+        Err(_) => Err(ScribrError::Parse(
+            "note, it is not valid UTF-8".to_string(),
+        )),
+    };
+    match parsed {
+        Ok(note) => Ok(Some(note)),
+        Err(ScribrError::Parse(msg)) if *strict => Err(ScribrError::Parse(format!(
+            "{} on line {}",
+            msg, line_number
+        ))),
+        Err(_) => {
+            skipped.push(line_number);
+            Ok(None)
+        }
+    }
+}
+
+fn report_skipped_lines(settings: &Settings, skipped: &mut [usize]) {
+    if skipped.is_empty() {
+        return;
+    }
+    skipped.sort_unstable();
+    if settings.verbosity > 0 {
+        let line_numbers: Vec<String> = skipped.iter().map(|n| n.to_string()).collect();
+        eprintln!("Skipped malformed lines: {}", line_numbers.join(", "));
     } else {
-        writeln!(file, "{}", full_note).unwrap();
+        eprintln!(
+            "Skipped {} malformed lines, run with -v to see which.",
+            skipped.len()
+        );
     }
 }
 
-pub fn list_notes(settings: Settings, count: &u8) {
+pub fn list_notes(
+    settings: Settings,
+    count: &u8,
+    strict: &bool,
+    tags: &[String],
+    any_tag: &bool,
+) -> ScribrResult<()> {
     if settings.verbosity > 0 {
         println!("📓 Printing your last {} notes:", count);
     }
 
-    let file = get_notes_file(settings.get_default_notebook_path());
-    let mut reader = RevLines::new(BufReader::new(file)).unwrap();
+    // malformed lines are flagged in place rather than reported after
+    let magic = MagicEngine::new(&settings);
+    let mut flagged = Vec::new();
+    let reader = read_lines_rev(settings.get_default_notebook_path())?
+        .filter(|(_, line)| !is_blank_line(line));
 
-    for i in 0..*count {
-        if let Some(val) = reader.next() {
-            println!("Note {}: {}", i, val);
+    let mut shown = 0;
+    for (line_number, line) in reader {
+        if shown >= *count {
+            break;
         }
+        match parse_note_line(line_number, &line, strict, &mut flagged)? {
+            Some(note) if note.has_tags(tags, any_tag) => println!(
+                "{}",
+                magic.for_display(&note).to_indented_string(NOTE_INDENT)
+            ),
+            Some(_) => continue,
+            // a malformed line has no tags to match
+            None if !tags.is_empty() => continue,
+            None => println!(
+                "⚠️ malformed line {}: {}",
+                line_number,
+                match &line {
+                    Ok(line) => line.to_string(),
+                    Err(raw_line) => String::from_utf8_lossy(raw_line).to_string(),
+                }
+            ),
+        }
+        shown += 1;
     }
+    Ok(())
 }
 
-pub fn search_notes(settings: Settings, term: &str, count: &u8) {
-    let file = get_notes_file(settings.get_default_notebook_path());
-    let reader = RevLines::new(BufReader::new(file)).unwrap();
+pub fn search_notes(
+    settings: Settings,
+    term: &str,
+    count: &u8,
+    strict: &bool,
+    all_notebooks: &bool,
+    tags: &[String],
+    any_tag: &bool,
+) -> ScribrResult<()> {
+    let notebook_paths = if *all_notebooks {
+        get_notebook_paths(&settings)?
+    } else {
+        vec![settings.get_default_notebook_path()]
+    };
     let matcher = SkimMatcherV2::default();
+    let magic = MagicEngine::new(&settings);
 
     println!("Searching notes with term \"{}\"...", term);
 
+    let mut skipped = Vec::new();
     let mut line_matches = Vec::new();
-    for line in reader {
-        let note = Note::new_from_line(&line);
-        let match_res = matcher.fuzzy_match(&note.note_value, term);
-        match match_res {
-            None => {}
-            Some(match_score) => line_matches.push((match_score, note)),
+    for notebook_path in notebook_paths {
+        let notebook_name = get_notebook_name(&notebook_path);
+        for (line_number, line) in read_lines_rev(notebook_path)? {
+            let note = match parse_note_line(line_number, &line, strict, &mut skipped)? {
+                Some(note) if note.has_tags(tags, any_tag) => note,
+                _ => continue,
+            };
+            let match_res = matcher.fuzzy_match(&note.note_value, term);
+            match match_res {
+                None => {}
+                Some(match_score) => line_matches.push((match_score, note, notebook_name.clone())),
+            }
         }
     }
 
@@ -90,13 +491,655 @@ pub fn search_notes(settings: Settings, term: &str, count: &u8) {
         );
     }
 
-    for _ in 0..print_count {
-        let val = line_matches.pop().unwrap();
-        println!("{} (Score: {})", val.1, val.0)
+    for val in line_matches.iter().rev().take(print_count as usize) {
+        if *all_notebooks {
+            print!("[{}] ", val.2);
+        }
+        println!(
+            "{} (Score: {})",
+            magic.for_display(&val.1).to_indented_string(NOTE_INDENT),
+            val.0
+        )
+    }
+    report_skipped_lines(&settings, &mut skipped);
+    Ok(())
+}
+
+/// Counts the words a note picks out, like its tags, with the last time each was used.
+fn count_note_words(
+    settings: &Settings,
+    words_of: fn(Note) -> Vec<String>,
+) -> ScribrResult<Vec<(String, usize, DateTime<Local>)>> {
+    // newest first, so the first time a word is seen is the last time it was used
+    let mut words: Vec<(String, usize, DateTime<Local>)> = Vec::new();
+    let mut skipped = Vec::new();
+    for (line_number, line) in read_lines_rev(settings.get_default_notebook_path())? {
+        let note = match parse_note_line(line_number, &line, &false, &mut skipped)? {
+            Some(note) => note,
+            None => continue,
+        };
+        let timestamp = note.timestamp;
+        for word in words_of(note) {
+            match words.iter_mut().find(|(name, _, _)| *name == word) {
+                Some((_, count, _)) => *count += 1,
+                None => words.push((word, 1, timestamp)),
+            }
+        }
+    }
+    report_skipped_lines(settings, &mut skipped);
+    words.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    Ok(words)
+}
+
+fn print_word_counts(sigil: char, words: Vec<(String, usize, DateTime<Local>)>) {
+    let width = words
+        .iter()
+        .map(|(name, _, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    for (name, count, last_used) in words {
+        println!(
+            "{}{:<width$}  {:>4}  last {}",
+            sigil,
+            name,
+            count,
+            last_used.to_rfc2822(),
+            width = width
+        );
+    }
+}
+
+pub fn list_tags(settings: Settings) -> ScribrResult<()> {
+    let tags = count_note_words(&settings, |note| note.tags)?;
+    if tags.is_empty() {
+        println!("No tags yet, add some to a note like #this.");
+    }
+    print_word_counts('#', tags);
+    Ok(())
+}
+
+pub fn list_people(settings: Settings, name: &Option<String>) -> ScribrResult<()> {
+    let name = match name {
+        Some(name) => normalise_mention(name),
+        None => {
+            let people = count_note_words(&settings, |note| note.mentions)?;
+            if people.is_empty() {
+                println!("Nobody mentioned yet, add someone to a note like @this.");
+            }
+            print_word_counts('@', people);
+            return Ok(());
+        }
+    };
+
+    let magic = MagicEngine::new(&settings);
+    let mut skipped = Vec::new();
+    let mut notes = Vec::new();
+    for (line_number, line) in read_lines_rev(settings.get_default_notebook_path())? {
+        match parse_note_line(line_number, &line, &false, &mut skipped)? {
+            Some(note) if note.mentions.contains(&name) => notes.push(note),
+            _ => continue,
+        }
+    }
+    if notes.is_empty() {
+        println!("No notes mention @{}.", name);
+    }
+    // read newest first, shown oldest first
+    for note in notes.iter().rev() {
+        println!(
+            "{}",
+            magic.for_display(note).to_indented_string(NOTE_INDENT)
+        );
+    }
+    report_skipped_lines(&settings, &mut skipped);
+    Ok(())
+}
+
+/// Reads a whole notebook, returning its content along with the notes that could be parsed.
+fn read_notebook(notes_path: &PathBuf) -> ScribrResult<(Vec<u8>, Vec<Note>)> {
+    let content = if notes_path.exists() {
+        fs::read(notes_path)
+            .map_err(|e| ScribrError::io(format!("Could not read {}", notes_path.display()), e))?
+    } else {
+        Vec::new()
+    };
+    let notes = notebook_lines(&content)
+        .into_iter()
+        .flatten()
+        .filter_map(|line| Note::new_from_line(line).ok())
+        .collect();
+    Ok((content, notes))
+}
+
+pub fn show_note(settings: Settings, id: &str) -> ScribrResult<()> {
+    let notes_path = settings.get_default_notebook_path();
+    let (_, notes) = read_notebook(&notes_path)?;
+    let note = find_note(&notes, id)?;
+
+    println!("Id:       {}", note.id);
+    println!("Notebook: {}", get_notebook_name(&notes_path));
+    println!("Taken:    {}", note.timestamp.to_rfc2822());
+    if let Some(edited_at) = note.edited_at() {
+        println!("Edited:   {}", edited_at.to_rfc2822());
+    }
+    if note.is_todo() {
+        match note.done_at() {
+            Some(done_at) => println!("Done:     {}", done_at.to_rfc2822()),
+            None => println!("Todo:     open"),
+        }
+    }
+    if let Some(due) = note.due() {
+        println!("Due:      {}", due.format(NOTE_DUE_FORMAT));
+    }
+    println!();
+    println!(
+        "{}",
+        MagicEngine::new(&settings).for_display(note).note_value
+    );
+
+    if !note.links.is_empty() {
+        println!();
+        println!("Links to:");
+        for target in &note.links {
+            match resolve_link(&notes, target) {
+                Ok(linked) => println!("  [{}] {}", linked.id, linked.title()),
+                Err(e) => println!("  ⚠️ {}", e),
+            }
+        }
+    }
+    let backlinks: Vec<&Note> = notes
+        .iter()
+        .filter(|other| {
+            other.links.iter().any(|target| {
+                resolve_link(&notes, target).is_ok_and(|linked| linked.is_copy_of(note))
+            })
+        })
+        .collect();
+    if !backlinks.is_empty() {
+        println!();
+        println!("Linked from:");
+        for other in backlinks {
+            println!("  [{}] {}", other.id, other.title());
+        }
+    }
+    Ok(())
+}
+
+pub fn list_todos(settings: Settings) -> ScribrResult<()> {
+    let magic = MagicEngine::new(&settings);
+    let mut open_count = 0;
+    for notebook_path in get_notebook_paths(&settings)? {
+        let notebook_name = get_notebook_name(&notebook_path);
+        let (_, notes) = read_notebook(&notebook_path)?;
+        for note in notes.iter().filter(|note| note.is_open_todo()) {
+            println!(
+                "[{}] {}",
+                notebook_name,
+                magic.for_display(note).to_indented_string(NOTE_INDENT)
+            );
+            open_count += 1;
+        }
+    }
+    if open_count == 0 {
+        println!("Nothing to do ✅");
+    }
+    Ok(())
+}
+
+pub fn show_agenda(settings: Settings, check: &bool) -> ScribrResult<()> {
+    let today = Local::now().date_naive();
+    let mut due_notes: Vec<(NaiveDate, String, Note)> = Vec::new();
+    for notebook_path in get_notebook_paths(&settings)? {
+        let notebook_name = get_notebook_name(&notebook_path);
+        let (_, notes) = read_notebook(&notebook_path)?;
+        for note in notes.into_iter().filter(|note| note.is_open_todo()) {
+            if let Some(due) = note.due() {
+                due_notes.push((due, notebook_name.clone(), note));
+            }
+        }
+    }
+    due_notes.sort_by(|a, b| {
+        a.0.cmp(&b.0)
+            .then_with(|| a.2.timestamp.cmp(&b.2.timestamp))
+    });
+    let overdue = due_notes.iter().filter(|(due, _, _)| *due < today).count();
+
+    if *check {
+        return match overdue {
+            0 => Ok(()),
+            overdue => Err(ScribrError::Overdue(overdue)),
+        };
+    }
+    if due_notes.is_empty() {
+        println!("Nothing due 🏖️");
+    }
+
+    let magic = MagicEngine::new(&settings);
+    let mut heading = String::new();
+    for (due, notebook_name, note) in due_notes {
+        let day_heading = if due < today {
+            "⚠️ Overdue".to_string()
+        } else if due == today {
+            "📅 Today".to_string()
+        } else {
+            due.format("%a %d %b %Y").to_string()
+        };
+        if day_heading != heading {
+            println!("{}", day_heading);
+            heading = day_heading;
+        }
+        println!(
+            "{}[{}] {}",
+            NOTE_INDENT,
+            notebook_name,
+            magic
+                .for_display(&note)
+                .to_indented_string(&NOTE_INDENT.repeat(2))
+        );
+    }
+    Ok(())
+}
+
+pub fn complete_todo(settings: Settings, id: &str) -> ScribrResult<()> {
+    // todos are listed across notebooks, so look for the id in all of them
+    let mut found = Vec::new();
+    for notebook_path in get_notebook_paths(&settings)? {
+        let (content, notes) = read_notebook(&notebook_path)?;
+        if let Ok(note) = find_note(&notes, id) {
+            found.push((notebook_path, content, note.clone()));
+        }
+    }
+    let (notes_path, content, note) = match found.len() {
+        1 => found.remove(0),
+        0 => return Err(ScribrError::NoteNotFound(format!("no note has id {}", id))),
+        n => {
+            return Err(ScribrError::NoteNotFound(format!(
+                "{} matches notes in {} notebooks, give more of the id",
+                id, n
+            )))
+        }
+    };
+    if let Some(done_at) = note.done_at() {
+        println!(
+            "Note {} was already done on {}.",
+            note.id,
+            done_at.to_rfc2822()
+        );
+        return Ok(());
+    }
+
+    let done = note.done();
+    journal::record(&format!("done {}", note.id), &[&notes_path])?;
+    write_notes_file(&notes_path, replace_note(&content, &note, Some(&done)))?;
+    println!("✅ {}", done.to_indented_string(NOTE_INDENT));
+    Ok(())
+}
+
+pub fn lint_notebooks(settings: Settings) -> ScribrResult<()> {
+    let mut broken_links = 0;
+    for notebook_path in get_notebook_paths(&settings)? {
+        let notebook_name = get_notebook_name(&notebook_path);
+        let (_, notes) = read_notebook(&notebook_path)?;
+        for note in &notes {
+            for target in &note.links {
+                if let Err(e) = resolve_link(&notes, target) {
+                    println!("{} [{}]: {}", notebook_name, note.id, e);
+                    broken_links += 1;
+                }
+            }
+        }
+    }
+
+    if broken_links > 0 {
+        return Err(ScribrError::Parse(format!(
+            "links, found {} that don't resolve",
+            broken_links
+        )));
+    }
+    println!("All links resolve.");
+    Ok(())
+}
+
+/// Saves new text for a note, from the editor if none is given, after checking with the user.
+fn update_note(
+    settings: &Settings,
+    note: &Note,
+    note_value: &Option<String>,
+    yes: &bool,
+) -> ScribrResult<()> {
+    let note_value = match note_value {
+        Some(note_value) => note_value.to_string(),
+        None => {
+            let title = format!(
+                "Editing note {} from {}",
+                note.id,
+                note.timestamp.to_rfc2822()
+            );
+            read_note_from_editor(settings, &title, &note.note_value)?
+        }
+    };
+    if note_value.trim().is_empty() {
+        println!("Nothing to save, the note was empty. Use scribr rm to remove a note.");
+        return Ok(());
+    }
+    if note_value == note.note_value {
+        println!("Note {} is unchanged.", note.id);
+        return Ok(());
+    }
+
+    let edited = note.edited(&note_value);
+    if !*yes {
+        println!("{}", note.to_indented_string(NOTE_INDENT));
+        println!("will become");
+        println!("{}", edited.to_indented_string(NOTE_INDENT));
+        if !fetch_user_confirm("Save this change?")? {
+            println!("Kept note {} as it was.", note.id);
+            return Ok(());
+        }
+    }
+
+    let notes_path = settings.get_default_notebook_path();
+    let (content, _) = read_notebook(&notes_path)?;
+    journal::record(&format!("edit {}", note.id), &[&notes_path])?;
+    write_notes_file(&notes_path, replace_note(&content, note, Some(&edited)))?;
+    println!("✏️ Edited note {}", edited.id);
+    Ok(())
+}
+
+pub fn edit_note(
+    settings: Settings,
+    id: &str,
+    note_value: &Option<String>,
+    yes: &bool,
+) -> ScribrResult<()> {
+    let (_, notes) = read_notebook(&settings.get_default_notebook_path())?;
+    let note = find_note(&notes, id)?;
+    update_note(&settings, note, note_value, yes)
+}
+
+pub fn amend_note(settings: Settings, note_value: &Option<String>, yes: &bool) -> ScribrResult<()> {
+    let (_, notes) = read_notebook(&settings.get_default_notebook_path())?;
+    let note = notes
+        .last()
+        .ok_or_else(|| ScribrError::NoteNotFound("the notebook has no notes".to_string()))?;
+    update_note(&settings, note, note_value, yes)
+}
+
+pub fn remove_note(settings: Settings, id: &str, yes: &bool) -> ScribrResult<()> {
+    let notes_path = settings.get_default_notebook_path();
+    let (content, notes) = read_notebook(&notes_path)?;
+    let note = find_note(&notes, id)?;
+
+    if !*yes {
+        println!("{}", note.to_indented_string(NOTE_INDENT));
+        if !fetch_user_confirm("Remove this note?")? {
+            println!("Kept note {}.", note.id);
+            return Ok(());
+        }
+    }
+    journal::record(&format!("rm {}", note.id), &[&notes_path])?;
+    write_notes_file(&notes_path, replace_note(&content, note, None))?;
+    println!("🗑️ Removed note {}", note.id);
+    Ok(())
+}
+
+/// Writes a file in full by writing a temp file next to it and moving that into place, so the
+/// file is never left half written.
+fn write_notes_file<C: AsRef<[u8]>>(path: &PathBuf, content: C) -> ScribrResult<()> {
+    let err_msg = format!("Could not write {}", path.display());
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let temp_path = path.with_file_name(format!(".{}.tmp", file_name));
+
+    let mut temp_file = File::create(&temp_path).map_err(|e| ScribrError::io(&err_msg, e))?;
+    temp_file
+        .write_all(content.as_ref())
+        .and_then(|_| temp_file.sync_all())
+        .map_err(|e| ScribrError::io(&err_msg, e))?;
+    fs::rename(&temp_path, path).map_err(|e| {
+        let _ = fs::remove_file(&temp_path);
+        ScribrError::io(&err_msg, e)
+    })
+}
+
+pub fn check_notebooks(settings: Settings, fix: &bool) -> ScribrResult<()> {
+    let mut problems = 0;
+    for notebook_path in get_notebook_paths(&settings)? {
+        let err_msg = format!("Could not read {}", notebook_path.display());
+        let content = fs::read(&notebook_path).map_err(|e| ScribrError::io(&err_msg, e))?;
+        let check = check_notebook(&content);
+
+        println!(
+            "{}: {} notes, {} bad lines, {} out of order, {} duplicates",
+            notebook_path.display(),
+            check.notes.len(),
+            check.bad_lines.len(),
+            check.out_of_order.len(),
+            check.duplicates.len()
+        );
+        if settings.verbosity > 0 {
+            for (line_number, _, reason) in &check.bad_lines {
+                println!("  line {}: {}", line_number, reason);
+            }
+            for line_number in &check.out_of_order {
+                println!("  line {}: older than the note before it", line_number);
+            }
+            for line_number in &check.duplicates {
+                println!("  line {}: duplicate note", line_number);
+            }
+        }
+        if check.problem_count() == 0 {
+            continue;
+        }
+        if !*fix {
+            problems += check.problem_count();
+            continue;
+        }
+
+        let backup_path =
+            notebook_path.with_extension(format!("{}.{}", NOTEBOOK_EXTENSION, BACKUP_EXTENSION));
+        let rejects_path =
+            notebook_path.with_extension(format!("{}.{}", NOTEBOOK_EXTENSION, REJECTS_EXTENSION));
+        journal::record(
+            &format!("fsck --fix {}", get_notebook_name(&notebook_path)),
+            &[&notebook_path, &rejects_path, &backup_path],
+        )?;
+        fs::copy(&notebook_path, &backup_path).map_err(|e| {
+            ScribrError::io(format!("Could not back up to {}", backup_path.display()), e)
+        })?;
+        println!("  backed up to {}", backup_path.display());
+
+        if !check.bad_lines.is_empty() {
+            let mut rejects = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&rejects_path)
+                .map_err(|e| {
+                    ScribrError::io(format!("Could not open {}", rejects_path.display()), e)
+                })?;
+            for (_, raw_line, _) in &check.bad_lines {
+                rejects
+                    .write_all(raw_line)
+                    .and_then(|_| rejects.write_all(b"\n"))
+                    .map_err(|e| {
+                        ScribrError::io(format!("Could not write {}", rejects_path.display()), e)
+                    })?;
+            }
+            println!(
+                "  moved {} bad lines to {}",
+                check.bad_lines.len(),
+                rejects_path.display()
+            );
+        }
+        write_notes_file(&notebook_path, check.fixed_content())?;
+        println!("  sorted and removed duplicates");
+    }
+
+    if problems > 0 {
+        return Err(ScribrError::Parse(format!(
+            "notebooks, found {} problems - run scribr fsck --fix to repair them",
+            problems
+        )));
+    }
+    Ok(())
+}
+
+/// Path of an existing notebook, by the name the user knows it by.
+fn get_existing_notebook_path(name: &str) -> ScribrResult<PathBuf> {
+    let notebook_path = get_scribr_home_dir().join(get_notebook_file_name(name)?);
+    if !notebook_path.is_file() {
+        return Err(ScribrError::Config(format!(
+            "no notebook named {} - see scribr notebook list",
+            name
+        )));
+    }
+    Ok(notebook_path)
+}
+
+/// Settings as they are saved, without any overrides from the command line.
+fn get_saved_settings() -> ScribrResult<Settings> {
+    get_settings_from_disk(Some(get_scribr_config_file()))
+}
+
+pub fn new_notebook(name: &str) -> ScribrResult<()> {
+    let notebook_path = get_scribr_home_dir().join(get_notebook_file_name(name)?);
+    if notebook_path.exists() {
+        return Err(ScribrError::Config(format!(
+            "a notebook named {} already exists",
+            name
+        )));
+    }
+    journal::record(&format!("notebook new {}", name), &[&notebook_path])?;
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&notebook_path)
+        .map_err(|e| {
+            ScribrError::io(
+                format!("Could not create notebook {}", notebook_path.display()),
+                e,
+            )
+        })?;
+    println!("📓 Created notebook {}", name);
+    Ok(())
+}
+
+pub fn list_notebooks(settings: Settings) -> ScribrResult<()> {
+    let default_notebook = settings.get_default_notebook_path();
+    for notebook_path in get_notebook_paths(&settings)? {
+        let (_, notes) = read_notebook(&notebook_path)?;
+        let marker = if notebook_path == default_notebook {
+            "*"
+        } else {
+            " "
+        };
+        println!(
+            "{} {} ({} notes)",
+            marker,
+            get_notebook_name(&notebook_path),
+            notes.len()
+        );
+    }
+    Ok(())
+}
+
+pub fn switch_notebook(name: &str) -> ScribrResult<()> {
+    let notebook_path = get_existing_notebook_path(name)?;
+    let mut settings = get_saved_settings()?;
+    settings.default_notebook = get_notebook_file_name(name)?;
+    journal::record(
+        &format!("notebook switch {}", name),
+        &[get_scribr_config_file()],
+    )?;
+    save_settings_to_disk(&settings)?;
+    println!("Switched to notebook {}", get_notebook_name(&notebook_path));
+    Ok(())
+}
+
+pub fn rename_notebook(name: &str, new_name: &str) -> ScribrResult<()> {
+    let notebook_path = get_existing_notebook_path(name)?;
+    let new_file_name = get_notebook_file_name(new_name)?;
+    let new_notebook_path = get_scribr_home_dir().join(&new_file_name);
+    if new_notebook_path.exists() {
+        return Err(ScribrError::Config(format!(
+            "a notebook named {} already exists",
+            new_name
+        )));
+    }
+    journal::record(
+        &format!("notebook rename {} {}", name, new_name),
+        &[
+            &notebook_path,
+            &new_notebook_path,
+            &get_scribr_config_file(),
+        ],
+    )?;
+    fs::rename(&notebook_path, &new_notebook_path)
+        .map_err(|e| ScribrError::io(format!("Could not rename {}", notebook_path.display()), e))?;
+
+    let mut settings = get_saved_settings()?;
+    if settings.get_default_notebook_path() == notebook_path {
+        settings.default_notebook = new_file_name;
+        save_settings_to_disk(&settings)?;
+    }
+    println!("Renamed notebook {} to {}", name, new_name);
+    Ok(())
+}
+
+pub fn delete_notebook(name: &str, yes: &bool) -> ScribrResult<()> {
+    let notebook_path = get_existing_notebook_path(name)?;
+    if get_saved_settings()?.get_default_notebook_path() == notebook_path {
+        return Err(ScribrError::Config(format!(
+            "{} is the default notebook, switch to another one before deleting it",
+            name
+        )));
+    }
+    if !*yes && !fetch_user_confirm(&format!("Delete notebook {} and all its notes?", name))? {
+        println!("Kept notebook {}", name);
+        return Ok(());
+    }
+    journal::record(&format!("notebook delete {}", name), &[&notebook_path])?;
+    fs::remove_file(&notebook_path)
+        .map_err(|e| ScribrError::io(format!("Could not delete {}", notebook_path.display()), e))?;
+    println!("🗑️ Deleted notebook {}", name);
+    Ok(())
+}
+
+pub fn undo() -> ScribrResult<()> {
+    match journal::undo_last()? {
+        Some(entry) => {
+            let taken_at = match entry.taken_at() {
+                Some(taken_at) => taken_at.to_rfc2822(),
+                None => entry.taken_at.clone(),
+            };
+            println!("↩️ Undid {} from {}", entry.operation, taken_at);
+            for file in entry.files {
+                let verb = if file.existed { "Put back" } else { "Removed" };
+                println!("  {} {}", verb, file.name);
+            }
+        }
+        None => println!("Nothing to undo."),
+    }
+    Ok(())
+}
+
+pub fn list_history(count: &u8) -> ScribrResult<()> {
+    let entries = journal::list_entries()?;
+    if entries.is_empty() {
+        println!("No operations in the journal.");
     }
+    for entry in entries.iter().take(*count as usize) {
+        let taken_at = match entry.taken_at() {
+            Some(taken_at) => taken_at.to_rfc2822(),
+            None => entry.taken_at.clone(),
+        };
+        println!("{}  {}", taken_at, entry.operation);
+    }
+    Ok(())
 }
 
-pub fn open_path() {
+pub fn open_path() -> ScribrResult<()> {
     let home_dir = get_scribr_home_dir();
     match open::that(&home_dir) {
         Ok(_) => {
@@ -106,14 +1149,21 @@ pub fn open_path() {
             println!("Couldnt automatically open {}", home_dir.display())
         }
     };
+    Ok(())
 }
 
-pub fn init(no_gh: &bool, force: &bool, gist_id: &Option<&str>) {
+pub fn init(
+    run_settings: Settings,
+    no_gh: &bool,
+    force: &bool,
+    gist_id: &Option<&str>,
+) -> ScribrResult<()> {
     let scribr_home_dir = get_scribr_home_dir();
     if scribr_home_dir.exists() && !*force {
-        panic!("Dir already exists at {} - aborting init.", {
+        return Err(ScribrError::Config(format!(
+            "Dir already exists at {} - aborting init.",
             scribr_home_dir.display()
-        })
+        )));
     }
 
     println!("Creating scribr dir in {}", scribr_home_dir.display());
@@ -122,22 +1172,34 @@ pub fn init(no_gh: &bool, force: &bool, gist_id: &Option<&str>) {
         "Could not create scribr dir at {}",
         scribr_home_dir.display()
     );
-    create_dir_all(&scribr_home_dir).expect(&*err_msg);
+    create_dir_all(&scribr_home_dir).map_err(|e| ScribrError::io(&err_msg, e))?;
 
     let files = if !*no_gh {
         println!("Setting up GitHub gist for backup...");
-        let access_token = get_gh_access_token_oauth();
-        let remote_gist = gh_fetch_scribr_gist(&access_token, gist_id)
-            .unwrap_or_else(|| gh_create_scribr_gist(&access_token, get_default_init_files(None)));
+        let gh_remote = GhRemote::new(None, run_settings.remote.as_ref())?;
+        let remote_gist = match gh_fetch_scribr_gist(&gh_remote, gist_id)? {
+            Some(gist) => gist,
+            None => gh_create_scribr_gist(&gh_remote, get_default_init_files(None))?,
+        };
 
+        // keep any enterprise urls or token file from the current settings
         let remote_gist_id = &*remote_gist.id;
-        let files = get_default_init_files(Some(remote_gist_id));
-        gh_push_gist_files(&access_token, remote_gist_id, files.clone());
+        let remote = RemoteSettings {
+            gist_id: Some(remote_gist_id.to_string()),
+            ..run_settings.remote.unwrap_or_default()
+        };
+        let files = get_default_init_files(Some(remote));
+        gh_push_gist_files(&gh_remote, remote_gist_id, files.clone())?;
         files
     } else {
         get_default_init_files(None)
     };
 
+    let paths: Vec<PathBuf> = files
+        .keys()
+        .map(|file_name| scribr_home_dir.join(file_name))
+        .collect();
+    journal::record("init", &paths)?;
     for (file_name, file) in files {
         let full_path = &scribr_home_dir.join(file_name);
         let err_msg = format!("failed writing file {}", &full_path.display());
@@ -146,53 +1208,194 @@ pub fn init(no_gh: &bool, force: &bool, gist_id: &Option<&str>) {
         } else {
             println!("Creating file {}", full_path.display());
         }
-        write(full_path, &file.content).expect(&*err_msg);
+        write(full_path, &file.content).map_err(|e| ScribrError::io(&err_msg, e))?;
     }
+    Ok(())
 }
 
-pub fn backup_notes(run_settings: Settings, include_settings: &bool) {
-    let gist_id = run_settings
-        .remote
-        .and_then(|remote| remote.gist_id)
-        .expect("bad result for gist id");
+fn get_local_backup_files(settings: &Settings, include_settings: &bool) -> ScribrResult<GhFiles> {
+    let mut paths = get_notebook_paths(settings)?;
+    if *include_settings {
+        paths.push(get_scribr_config_file());
+    }
 
-    let path = get_scribr_home_dir();
     let mut files = GhFiles::new();
-    if let Ok(entries) = fs::read_dir(path) {
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let file = entry.path();
-                let file_name = file
-                    .file_name()
-                    .and_then(|name| name.to_str())
-                    .expect("Could not get file name!");
-                let content = read_file(&file).expect("could not extract file content");
-                if file_name == SCRIBR_CONFIG_FILE_NAME && !*include_settings {
-                    continue;
+    for path in paths {
+        let file_name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(file_name) => file_name.to_string(),
+            None => continue,
+        };
+        if let Some(content) = read_file(&path)? {
+            files.insert(file_name, GhFile::from(content));
+        }
+    }
+    Ok(files)
+}
+
+fn print_files_diff(local_files: &GhFiles, remote_files: &GhFiles) -> ScribrResult<()> {
+    let file_names: BTreeSet<&String> = local_files.keys().chain(remote_files.keys()).collect();
+    for file_name in file_names {
+        let local = local_files.get(file_name).map(|file| file.content.as_str());
+        let remote = remote_files
+            .get(file_name)
+            .map(|file| file.content.as_str());
+        match (local, remote) {
+            (Some(_), None) => println!("{}: only on this machine", file_name),
+            (None, Some(_)) => println!("{}: only in the gist", file_name),
+            (Some(local), Some(remote)) if local == remote => {
+                println!("{}: no changes", file_name)
+            }
+            (Some(local), Some(remote)) if file_name == SCRIBR_CONFIG_FILE_NAME => {
+                println!("{}:", file_name);
+                for (key, local_value, remote_value) in diff_settings(local, remote) {
+                    println!(
+                        "  ~ {}: {} (local) / {} (gist)",
+                        key,
+                        local_value.unwrap_or_else(|| "<unset>".to_string()),
+                        remote_value.unwrap_or_else(|| "<unset>".to_string())
+                    );
+                }
+            }
+            (Some(local), Some(remote)) => {
+                let diff = diff_notes(local, remote);
+                println!(
+                    "{}: {} notes only on this machine, {} notes only in the gist, {} changed",
+                    file_name,
+                    diff.local_only.len(),
+                    diff.remote_only.len(),
+                    diff.changed.len()
+                );
+                for note in diff.local_only {
+                    println!("  < {}", note);
+                }
+                for note in diff.remote_only {
+                    println!("  > {}", note);
+                }
+                for (local_note, remote_note) in diff.changed {
+                    println!("  ~ {}", local_note);
+                    println!("    {}", remote_note);
+                }
+                for line in diff.local_unparsed {
+                    println!("  ! {} (not a note, on this machine)", line);
+                }
+                for line in diff.remote_unparsed {
+                    println!("  ! {} (not a note, in the gist)", line);
                 }
-                files.insert(file_name.to_string(), GhFile::from(content));
             }
+            (None, None) => {}
+        }
+    }
+    Ok(())
+}
+
+pub fn backup_notes(
+    run_settings: Settings,
+    include_settings: &bool,
+    dry_run: &bool,
+    token_file: &Option<PathBuf>,
+) -> ScribrResult<()> {
+    let gist_id = run_settings.remote_gist_id()?;
+    let gh_remote = GhRemote::new(token_file.as_ref(), run_settings.remote.as_ref())?;
+
+    let files = get_local_backup_files(&run_settings, include_settings)?;
+    let too_big: Vec<&String> = files
+        .iter()
+        .filter(|(_, file)| file.content.len() > GH_GIST_MAX_FILE_SIZE)
+        .map(|(file_name, _)| file_name)
+        .collect();
+    if !too_big.is_empty() {
+        for file_name in too_big {
+            println!(
+                "{} is over the {} MB limit for a gist file.",
+                file_name,
+                GH_GIST_MAX_FILE_SIZE / 1024 / 1024
+            );
         }
+        return Err(ScribrError::Config(
+            "notebooks too big to back up - split them up and try again".to_string(),
+        ));
+    }
+    if *dry_run {
+        let remote_files = gh_pull_gist_files(&gh_remote, &gist_id, None)?;
+        print_files_diff(&files, &remote_files)?;
+        println!("Dry run - nothing was pushed to the gist.");
+        return Ok(());
     }
+
     println!("We will back up the following files");
     for f_name in files.keys() {
         println!("{}", f_name)
     }
-    let access_token = get_gh_access_token_oauth();
-    gh_push_gist_files(&access_token, &gist_id, files);
+    gh_push_gist_files(&gh_remote, &gist_id, files)?;
+    Ok(())
 }
 
-pub fn restore_notes(run_settings: Settings, force: &bool, include_settings: &bool) {
-    let gist_id = run_settings
-        .remote
-        .and_then(|remote| remote.gist_id)
-        .expect("bad result for gist id");
-    let access_token = get_gh_access_token_oauth();
-    let files = gh_pull_gist_files(&access_token, &gist_id);
+pub fn restore_notes(
+    run_settings: Settings,
+    force: &bool,
+    merge: &bool,
+    include_settings: &bool,
+    dry_run: &bool,
+    revision: &Option<String>,
+    token_file: &Option<PathBuf>,
+) -> ScribrResult<()> {
+    let gist_id = run_settings.remote_gist_id()?;
+    let gh_remote = GhRemote::new(token_file.as_ref(), run_settings.remote.as_ref())?;
+    let files = gh_pull_gist_files(&gh_remote, &gist_id, revision.as_deref())?;
     let home_dir = get_scribr_home_dir();
 
+    if *dry_run {
+        print_files_diff(&get_local_backup_files(&run_settings, &true)?, &files)?;
+    }
+    let action = |verb: &str| {
+        if *dry_run {
+            format!("Would {}", verb.to_lowercase())
+        } else {
+            verb.to_string()
+        }
+    };
+
+    if !*dry_run {
+        let operation = match revision {
+            Some(revision) => format!("gh restore --revision {}", revision),
+            None => "gh restore".to_string(),
+        };
+        let paths: Vec<PathBuf> = files
+            .keys()
+            .map(|file_name| home_dir.join(file_name))
+            .collect();
+        journal::record(&operation, &paths)?;
+    }
+    // work out every file before writing any, so a bad file can't leave a restore half done
+    let mut writes: Vec<(PathBuf, String)> = Vec::new();
     for (file_name, file_data) in files {
         let full_path = home_dir.join(&file_name);
+        if *merge && full_path.exists() && file_name != SCRIBR_CONFIG_FILE_NAME {
+            let local_content = read_file(&full_path)?.unwrap_or_default();
+            let merged = merge_notes(&local_content, &file_data.content);
+            println!(
+                "{} file {}: {} notes added and {} updated from the gist, {} notes only on this machine",
+                action("Merged"),
+                full_path.display(),
+                merged.remote_only,
+                merged.updated,
+                merged.local_only
+            );
+            if !merged.local_unparsed.is_empty() {
+                println!(
+                    "  kept {} lines that aren't notes at the end, see scribr fsck",
+                    merged.local_unparsed.len()
+                );
+            }
+            if merged.remote_skipped > 0 {
+                println!(
+                    "  skipped {} lines in the gist that aren't notes",
+                    merged.remote_skipped
+                );
+            }
+            writes.push((full_path, merged.to_file_content()));
+            continue;
+        }
         if full_path.exists() && !*force {
             println!(
                 "Not overwriting file {} as --force was not applied",
@@ -200,18 +1403,129 @@ pub fn restore_notes(run_settings: Settings, force: &bool, include_settings: &bo
             );
             continue;
         }
-        if !*force && !*include_settings && &file_name == SCRIBR_CONFIG_FILE_NAME {
+        if !*force && !*include_settings && file_name == SCRIBR_CONFIG_FILE_NAME {
             println!(
                 "not overwriting settings file {}  as --include-settings was not passed",
                 full_path.display()
             );
             continue;
         }
-        println!("Overwriting file {}", full_path.display());
-        write(full_path, file_data.content).unwrap();
+        println!("{} file {}", action("Overwrite"), full_path.display());
+        writes.push((full_path, file_data.content));
+    }
+    if !*dry_run {
+        for (full_path, content) in writes {
+            write_notes_file(&full_path, content)?;
+        }
+    }
+    if *dry_run {
+        println!("Dry run - no local files were changed.");
+    }
+    Ok(())
+}
+
+pub fn diff_remote(run_settings: Settings, token_file: &Option<PathBuf>) -> ScribrResult<()> {
+    let gist_id = run_settings.remote_gist_id()?;
+    let gh_remote = GhRemote::new(token_file.as_ref(), run_settings.remote.as_ref())?;
+    let remote_files = gh_pull_gist_files(&gh_remote, &gist_id, None)?;
+    print_files_diff(
+        &get_local_backup_files(&run_settings, &true)?,
+        &remote_files,
+    )
+}
+
+pub fn list_remote_gists(run_settings: Settings, token_file: &Option<PathBuf>) -> ScribrResult<()> {
+    let gh_remote = GhRemote::new(token_file.as_ref(), run_settings.remote.as_ref())?;
+    let current_gist_id = run_settings.remote.and_then(|remote| remote.gist_id);
+    let gists = gh_list_scribr_gists(&gh_remote)?;
+
+    println!(
+        "{} gists with a {} file:",
+        gists.len(),
+        SCRIBR_CONFIG_FILE_NAME
+    );
+    for gist in gists {
+        let marker = if current_gist_id.as_deref() == Some(gist.id.as_str()) {
+            "*"
+        } else {
+            " "
+        };
+        println!(
+            "{} {} updated {} - {} files - {}",
+            marker,
+            gist.id,
+            gist.updated_at,
+            gist.files.len(),
+            gist.html_url
+        );
+    }
+    println!("Use one with scribr init --gist-id <id>");
+    Ok(())
+}
+
+pub fn list_remote_history(
+    run_settings: Settings,
+    token_file: &Option<PathBuf>,
+) -> ScribrResult<()> {
+    let gist_id = run_settings.remote_gist_id()?;
+    let gh_remote = GhRemote::new(token_file.as_ref(), run_settings.remote.as_ref())?;
+    let gist = gh_fetch_existing_scribr_gist(&gh_remote, &gist_id)?;
+
+    println!("{} revisions, newest first:", gist.history.len());
+    for revision in gist.history {
+        println!(
+            "{} {} ({} changes: +{} -{})",
+            revision.version,
+            revision.committed_at,
+            revision.change_status.total,
+            revision.change_status.additions,
+            revision.change_status.deletions
+        );
+    }
+    println!("Restore one with scribr gh restore --revision <version>");
+    Ok(())
+}
+
+pub fn login(run_settings: Settings) -> ScribrResult<()> {
+    gh_login(&GhRemote::new(None, run_settings.remote.as_ref())?)?;
+    println!("Logged in to GitHub, your access token is saved for future backups.");
+    Ok(())
+}
+
+pub fn logout() -> ScribrResult<()> {
+    let token_file = get_gh_token_file();
+    if clear_gh_access_token(&token_file) {
+        println!("Removed saved GitHub access token {}", token_file.display());
+    } else {
+        println!("Not logged in to GitHub.");
     }
+    Ok(())
 }
 
-pub fn echo_under_construction(_settings: Settings) {
-    println!("🔨🔨🔨 Currently under construction 🔨🔨🔨")
+pub fn login_status(run_settings: Settings, token_file: &Option<PathBuf>) -> ScribrResult<()> {
+    let gh_remote = GhRemote::new(token_file.as_ref(), run_settings.remote.as_ref())?;
+    let (token, source) = match gh_remote.token {
+        GhToken::Provided {
+            ref token,
+            ref source,
+        } => (token.to_string(), source.to_string()),
+        GhToken::Saved => {
+            let saved_token_file = get_gh_token_file();
+            match load_gh_access_token(&saved_token_file)? {
+                None => {
+                    println!("Not logged in to GitHub - run scribr gh login");
+                    return Ok(());
+                }
+                Some(token) => (token, saved_token_file.display().to_string()),
+            }
+        }
+    };
+    let user = gh_fetch_user(&gh_remote, &token).map_err(|e| {
+        ScribrError::Auth(format!("the token from {} is not usable ({})", source, e))
+    })?;
+    println!(
+        "Logged in to GitHub as {} (token from {})",
+        user.login, source
+    );
+    Ok(())
 }
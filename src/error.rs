@@ -0,0 +1,88 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+
+use reqwest::StatusCode;
+
+pub type ScribrResult<T> = Result<T, ScribrError>;
+
+#[derive(Debug)]
+pub enum ScribrError {
+    /// Reading or writing a file went wrong.
+    Io { context: String, source: io::Error },
+    /// A note or a file could not be understood.
+    Parse(String),
+    /// The scribr settings or setup are wrong.
+    Config(String),
+    /// GitHub did not accept, or we could not get, an access token.
+    Auth(String),
+    /// Talking to GitHub failed.
+    Network(String),
+    /// A GitHub command was run without a gist set up in the settings.
+    RemoteNotConfigured,
+    /// No note, or more than one, has the id that was asked for.
+    NoteNotFound(String),
+    /// `agenda --check` found this many todos past their due date.
+    Overdue(usize),
+}
+
+impl ScribrError {
+    pub fn io(context: impl Into<String>, source: io::Error) -> ScribrError {
+        ScribrError::Io {
+            context: context.into(),
+            source,
+        }
+    }
+
+    /// Exit code for the process, one per kind of error so scripts can tell them apart.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ScribrError::Io { .. } => 3,
+            ScribrError::Parse(_) => 4,
+            ScribrError::Config(_) => 5,
+            ScribrError::Auth(_) => 6,
+            ScribrError::Network(_) => 7,
+            ScribrError::RemoteNotConfigured => 8,
+            ScribrError::NoteNotFound(_) => 9,
+            ScribrError::Overdue(_) => 10,
+        }
+    }
+}
+
+impl Display for ScribrError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScribrError::Io { context, source } => write!(f, "{}: {}", context, source),
+            ScribrError::Parse(msg) => write!(f, "Could not parse {}", msg),
+            ScribrError::Config(msg) => write!(f, "Bad config: {}", msg),
+            ScribrError::Auth(msg) => write!(f, "GitHub auth failed: {}", msg),
+            ScribrError::Network(msg) => {
+                write!(f, "Something went wrong talking to GitHub: {}", msg)
+            }
+            ScribrError::RemoteNotConfigured => write!(
+                f,
+                "No gist is set up for backups - run scribr init or set remote.gist_id"
+            ),
+            ScribrError::NoteNotFound(msg) => write!(f, "Note not found: {}", msg),
+            ScribrError::Overdue(count) => {
+                write!(f, "{} todos are overdue - see scribr agenda", count)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ScribrError {}
+
+impl From<reqwest::Error> for ScribrError {
+    fn from(err: reqwest::Error) -> Self {
+        match err.status() {
+            Some(StatusCode::UNAUTHORIZED) => ScribrError::Auth(err.to_string()),
+            _ => ScribrError::Network(err.to_string()),
+        }
+    }
+}
+
+impl From<serde_yaml::Error> for ScribrError {
+    fn from(err: serde_yaml::Error) -> Self {
+        ScribrError::Config(err.to_string())
+    }
+}
@@ -1,16 +1,22 @@
-use std::collections::HashMap;
-use std::fs::File as Fs;
-use std::io::{BufReader, Read};
-use std::path::PathBuf;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{read_dir, File as Fs};
+use std::io::{stdin, stdout, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
 
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, Weekday};
 use dirs::home_dir;
+use serde_yaml::Value;
 
-use crate::model::{File, Settings, SCRIBR_CONFIG_FILE_NAME};
+use crate::error::{ScribrError, ScribrResult};
+use crate::model::{
+    File, Note, RemoteSettings, Settings, NOTE_DUE_FORMAT, SCRIBR_CONFIG_FILE_NAME,
+    SCRIBR_GH_TOKEN_FILE_NAME,
+};
 
-pub fn get_default_init_files(gist_id: Option<&str>) -> HashMap<String, File> {
-    let settings = match gist_id {
+pub fn get_default_init_files(remote: Option<RemoteSettings>) -> HashMap<String, File> {
+    let settings = match remote {
         None => Settings::default(),
-        Some(gist_id) => Settings::new_with_gist_id(gist_id),
+        Some(remote) => Settings::new_with_remote(remote),
     };
     HashMap::from([(
         SCRIBR_CONFIG_FILE_NAME.to_string(),
@@ -28,25 +34,166 @@ pub fn get_scribr_config_file() -> PathBuf {
     get_scribr_home_dir().join(SCRIBR_CONFIG_FILE_NAME)
 }
 
-pub fn read_file(file_path: &PathBuf) -> Option<String> {
+pub fn get_gh_token_file() -> PathBuf {
+    get_scribr_home_dir().join(SCRIBR_GH_TOKEN_FILE_NAME)
+}
+
+pub fn read_file(file_path: &PathBuf) -> ScribrResult<Option<String>> {
     if file_path.exists() {
-        let mut file = Fs::open(file_path).expect("bad open of settings file");
+        let err_msg = format!("Could not read {}", file_path.display());
+        let mut file = Fs::open(file_path).map_err(|e| ScribrError::io(&err_msg, e))?;
         let mut contents = String::new();
-        file.read_to_string(&mut contents).unwrap();
-        Some(contents)
+        file.read_to_string(&mut contents)
+            .map_err(|e| ScribrError::io(&err_msg, e))?;
+        Ok(Some(contents))
     } else {
-        None
+        Ok(None)
+    }
+}
+
+/// Reads one line from stdin without its line ending, or `None` once stdin is closed.
+pub fn read_user_line() -> ScribrResult<Option<String>> {
+    let _ = stdout().flush();
+    let mut input = String::new();
+    let read = stdin()
+        .lock()
+        .read_line(&mut input)
+        .map_err(|e| ScribrError::io("Could not read from stdin", e))?;
+    if read == 0 {
+        return Ok(None);
     }
+    Ok(Some(input.trim_end_matches(['\r', '\n']).to_string()))
 }
 
-pub fn get_settings_from_disk(settings_file: Option<PathBuf>) -> Settings {
+/// Asks a yes or no question until it gets an answer, treating a closed stdin as no.
+pub fn fetch_user_confirm(prompt: &str) -> ScribrResult<bool> {
+    loop {
+        print!("{} (y/n) ", prompt);
+        match read_user_line()?.as_deref().map(str::trim) {
+            Some("y") | Some("yes") => return Ok(true),
+            Some("n") | Some("no") | None => return Ok(false),
+            Some(_) => continue,
+        }
+    }
+}
+
+/// Works out the day a `!due(...)` or `--due` is for: `YYYY-MM-DD`, `today`, `tomorrow` or a
+/// weekday, which means the next one from `today`, counting today.
+pub fn parse_due(spec: &str, today: NaiveDate) -> ScribrResult<NaiveDate> {
+    let spec = spec.trim().to_lowercase();
+    if let Ok(due) = NaiveDate::parse_from_str(&spec, NOTE_DUE_FORMAT) {
+        return Ok(due);
+    }
+    match spec.as_str() {
+        "today" => return Ok(today),
+        "tomorrow" => return Ok(today + Duration::days(1)),
+        _ => {}
+    }
+    match spec.parse::<Weekday>() {
+        Ok(weekday) => {
+            let days_ahead =
+                (weekday.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+            Ok(today + Duration::days(days_ahead as i64))
+        }
+        Err(_) => Err(ScribrError::Parse(format!(
+            "due date \"{}\", use YYYY-MM-DD, today, tomorrow or a weekday",
+            spec
+        ))),
+    }
+}
+
+/// Time tracked by `scribr start`, from the start note to the stop note pointing back at it.
+pub struct TimerSpan {
+    pub start: Note,
+    /// When the stop note was written, or `None` if the timer is still running.
+    pub stopped_at: Option<DateTime<Local>>,
+}
+
+impl TimerSpan {
+    pub fn duration(&self, now: DateTime<Local>) -> Duration {
+        self.stopped_at.unwrap_or(now) - self.start.timestamp
+    }
+}
+
+/// Pairs up the start and stop notes in a notebook, in the order the timers were started.
+pub fn pair_timers(notes: &[Note]) -> Vec<TimerSpan> {
+    notes
+        .iter()
+        .filter(|note| note.is_timer_start())
+        .map(|start| TimerSpan {
+            start: start.clone(),
+            stopped_at: notes
+                .iter()
+                .find(|note| note.stops() == Some(start.id.as_str()))
+                .map(|stop| stop.timestamp),
+        })
+        .collect()
+}
+
+/// Adds up the time on each tag of the timers, untagged time going under `untagged`. Biggest
+/// first.
+pub fn total_time_by_tag(
+    spans: &[TimerSpan],
+    now: DateTime<Local>,
+    untagged: &str,
+) -> Vec<(String, Duration)> {
+    let mut totals: Vec<(String, Duration)> = Vec::new();
+    for span in spans {
+        let duration = span.duration(now);
+        let tags = if span.start.tags.is_empty() {
+            vec![untagged.to_string()]
+        } else {
+            span.start.tags.clone()
+        };
+        for tag in tags {
+            match totals.iter_mut().find(|(name, _)| *name == tag) {
+                Some((_, total)) => *total = *total + duration,
+                None => totals.push((tag, duration)),
+            }
+        }
+    }
+    totals.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    totals
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.num_minutes().max(0);
+    format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
+
+/// Prefix for the header lines scribr writes at the top of a note opened in an editor.
+pub const EDITOR_COMMENT_PREFIX: &str = "#";
+/// Last line of the editor header, everything above it is dropped from the note.
+pub const EDITOR_SCISSORS_LINE: &str = "# ------------------------ >8 ------------------------";
+
+/// Drops the header scribr wrote above the scissors line, and any blank lines around what's left
+/// of a note written in an editor. Lines in the note itself are kept even if they start with a
+/// `#tag`. If the scissors line was deleted, the whole file is the note.
+pub fn strip_editor_header(content: &str) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    let note_start = lines
+        .iter()
+        .position(|line| line.trim_end() == EDITOR_SCISSORS_LINE)
+        .map_or(0, |scissors| scissors + 1);
+    lines[note_start..]
+        .join("\n")
+        .trim_matches('\n')
+        .trim_end()
+        .to_string()
+}
+
+pub fn get_settings_from_disk(settings_file: Option<PathBuf>) -> ScribrResult<Settings> {
     let settings = match settings_file {
         None => Settings::default(),
         Some(settings_file) => {
             if settings_file.exists() {
-                let file = Fs::open(settings_file).expect("bad open of settings file");
+                let file = Fs::open(&settings_file).map_err(|e| {
+                    ScribrError::io(format!("Could not open {}", settings_file.display()), e)
+                })?;
                 let reader = BufReader::new(file);
-                serde_yaml::from_reader(reader).expect("Bad format of settings file")
+                serde_yaml::from_reader(reader).map_err(|e| {
+                    ScribrError::Config(format!("{} - {}", settings_file.display(), e))
+                })?
             } else {
                 println!(
                     "Cannot locate settings file at {} have you deleted it?",
@@ -57,12 +204,393 @@ pub fn get_settings_from_disk(settings_file: Option<PathBuf>) -> Settings {
         }
     };
     if let Some(remote) = &settings.remote {
-        if remote.gist_id == None {
+        if remote.gist_id.is_none() {
             println!("Warning! remote settings are missing data! backups may fail")
         }
     }
 
-    settings
+    Ok(settings)
+}
+
+pub const NOTEBOOK_EXTENSION: &str = "txt";
+pub const REJECTS_EXTENSION: &str = "rejects";
+pub const BACKUP_EXTENSION: &str = "bak";
+
+/// Writes the settings to the scribr config file, replacing what was there.
+pub fn save_settings_to_disk(settings: &Settings) -> ScribrResult<()> {
+    let settings_file = get_scribr_config_file();
+    std::fs::write(&settings_file, serde_yaml::to_string(settings)?)
+        .map_err(|e| ScribrError::io(format!("Could not write {}", settings_file.display()), e))
+}
+
+/// File name for a notebook, so `work` is stored as `work.txt` in the scribr dir.
+pub fn get_notebook_file_name(name: &str) -> ScribrResult<String> {
+    let name = name
+        .strip_suffix(&format!(".{}", NOTEBOOK_EXTENSION))
+        .unwrap_or(name);
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || ['-', '_', '.'].contains(&c));
+    if !valid {
+        return Err(ScribrError::Config(format!(
+            "\"{}\" is not a notebook name, use letters, numbers, - _ and . only",
+            name
+        )));
+    }
+    Ok(format!("{}.{}", name, NOTEBOOK_EXTENSION))
+}
+
+/// Name of a notebook as the user sees it, the file name without the extension.
+pub fn get_notebook_name(notebook_path: &Path) -> String {
+    notebook_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Lists the notebooks in the scribr dir, the `.txt` files plus the default notebook.
+pub fn get_notebook_paths(settings: &Settings) -> ScribrResult<Vec<PathBuf>> {
+    let home_dir = get_scribr_home_dir();
+    let entries = read_dir(&home_dir)
+        .map_err(|e| ScribrError::io(format!("Could not read {}", home_dir.display()), e))?;
+
+    let default_notebook = settings.get_default_notebook_path();
+    let mut notebooks: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && (path == &default_notebook
+                    || path.extension().and_then(|ext| ext.to_str()) == Some(NOTEBOOK_EXTENSION))
+        })
+        .collect();
+    notebooks.sort();
+    Ok(notebooks)
+}
+
+/// What is wrong with a notebook, by line number.
+#[derive(Default)]
+pub struct NotebookCheck {
+    /// The notes that could be read, in file order.
+    pub notes: Vec<Note>,
+    /// Lines that are not notes, kept as raw bytes with the reason.
+    pub bad_lines: Vec<(usize, Vec<u8>, String)>,
+    pub out_of_order: Vec<usize>,
+    pub duplicates: Vec<usize>,
+}
+
+impl NotebookCheck {
+    pub fn problem_count(&self) -> usize {
+        self.bad_lines.len() + self.out_of_order.len() + self.duplicates.len()
+    }
+
+    /// The notebook with bad lines and duplicates dropped, sorted by time.
+    pub fn fixed_content(&self) -> String {
+        let mut notes: Vec<&Note> = Vec::new();
+        for note in &self.notes {
+            if !notes.contains(&note) {
+                notes.push(note);
+            }
+        }
+        notes.sort_by_key(|note| note.timestamp);
+        notes.iter().map(|note| format!("{}\n", note)).collect()
+    }
+}
+
+pub fn check_notebook(content: &[u8]) -> NotebookCheck {
+    let mut check = NotebookCheck::default();
+    for (i, raw_line) in content.split(|byte| *byte == b'\n').enumerate() {
+        let line_number = i + 1;
+        let line = match std::str::from_utf8(raw_line) {
+            Ok(line) => line.trim_end_matches('\r'),
+            Err(_) => {
+                let reason = "invalid UTF-8".to_string();
+                check
+                    .bad_lines
+                    .push((line_number, raw_line.to_vec(), reason));
+                continue;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        match Note::new_from_line(line) {
+            Ok(note) => {
+                if check.notes.contains(&note) {
+                    check.duplicates.push(line_number);
+                } else if check
+                    .notes
+                    .last()
+                    .is_some_and(|last| last.timestamp > note.timestamp)
+                {
+                    check.out_of_order.push(line_number);
+                }
+                check.notes.push(note);
+            }
+            Err(e) => check
+                .bad_lines
+                .push((line_number, raw_line.to_vec(), e.to_string())),
+        }
+    }
+    check
+}
+
+/// Finds a note by its id, or by the start of one as long as only one note matches.
+pub fn find_note<'a>(notes: &'a [Note], id: &str) -> ScribrResult<&'a Note> {
+    if let Some(note) = notes.iter().find(|note| note.id == id) {
+        return Ok(note);
+    }
+    let mut matches = notes.iter().filter(|note| note.id.starts_with(id));
+    match (matches.next(), matches.count()) {
+        (Some(note), 0) if !id.is_empty() => Ok(note),
+        (Some(_), others) if !id.is_empty() => Err(ScribrError::NoteNotFound(format!(
+            "{} matches {} notes, give more of the id",
+            id,
+            others + 1
+        ))),
+        _ => Err(ScribrError::NoteNotFound(format!("no note has id {}", id))),
+    }
+}
+
+/// Finds the note a `[[link]]` points at, by its full id or else by its title, ignoring case.
+pub fn resolve_link<'a>(notes: &'a [Note], target: &str) -> ScribrResult<&'a Note> {
+    if let Some(note) = notes.iter().find(|note| note.id == target) {
+        return Ok(note);
+    }
+    let mut matches = notes
+        .iter()
+        .filter(|note| note.title().eq_ignore_ascii_case(target));
+    match (matches.next(), matches.count()) {
+        (Some(note), 0) => Ok(note),
+        (Some(_), others) => Err(ScribrError::NoteNotFound(format!(
+            "[[{}]] matches the title of {} notes, link to an id instead",
+            target,
+            others + 1
+        ))),
+        (None, _) => Err(ScribrError::NoteNotFound(format!(
+            "[[{}]] is not the id or title of a note",
+            target
+        ))),
+    }
+}
+
+/// Splits notebook content into lines, as text, or as the raw bytes of a line that isn't UTF-8.
+pub fn notebook_lines(content: &[u8]) -> Vec<Result<&str, &[u8]>> {
+    let content = content.strip_suffix(b"\n").unwrap_or(content);
+    if content.is_empty() {
+        return Vec::new();
+    }
+    content
+        .split(|byte| *byte == b'\n')
+        .map(|raw_line| {
+            std::str::from_utf8(raw_line)
+                .map(|line| line.trim_end_matches('\r'))
+                .map_err(|_| raw_line)
+        })
+        .collect()
+}
+
+/// Rewrites a notebook with the note `id` swapped for `replacement`, or dropped when there is
+/// none. Lines that are not notes are kept as they are and notes without a stored id get one.
+pub fn replace_note(content: &[u8], target: &Note, replacement: Option<&Note>) -> Vec<u8> {
+    let mut new_content = Vec::with_capacity(content.len());
+    for line in notebook_lines(content) {
+        match line.map(|line| (line, Note::new_from_line(line))) {
+            Ok((_, Ok(note))) if note.is_copy_of(target) => match replacement {
+                Some(replacement) => new_content.extend(replacement.to_string().as_bytes()),
+                None => continue,
+            },
+            Ok((_, Ok(note))) => new_content.extend(note.to_string().as_bytes()),
+            Ok((line, Err(_))) => new_content.extend(line.as_bytes()),
+            Err(raw_line) => new_content.extend(raw_line),
+        }
+        new_content.push(b'\n');
+    }
+    new_content
+}
+
+pub struct NotesDiff {
+    pub local_only: Vec<Note>,
+    pub remote_only: Vec<Note>,
+    /// Notes on both sides that differ, as the local copy then the remote one.
+    pub changed: Vec<(Note, Note)>,
+    /// Lines on each side that aren't notes, like hand edits or conflict markers.
+    pub local_unparsed: Vec<String>,
+    pub remote_unparsed: Vec<String>,
+}
+
+pub struct NotesMerge {
+    pub notes: Vec<Note>,
+    /// Notes that were only in the local notebook.
+    pub local_only: usize,
+    /// Notes that were only in the remote notebook.
+    pub remote_only: usize,
+    /// Notes on both sides where the remote copy was changed later and replaced the local one.
+    pub updated: usize,
+    /// Local lines that aren't notes, kept as they were at the end of the notebook for fsck.
+    pub local_unparsed: Vec<String>,
+    /// Remote lines that aren't notes, which are left out.
+    pub remote_skipped: usize,
+}
+
+impl NotesMerge {
+    pub fn to_file_content(&self) -> String {
+        self.notes
+            .iter()
+            .map(|note| note.to_string())
+            .chain(self.local_unparsed.iter().cloned())
+            .map(|line| format!("{}\n", line))
+            .collect()
+    }
+}
+
+/// Parses the notes in a notebook, keeping only the newest copy of a note that is there twice.
+/// Lines that aren't notes are handed back as they are.
+fn parse_unique_notes(content: &str) -> (Vec<Note>, Vec<String>) {
+    let mut notes: Vec<Note> = Vec::new();
+    let mut unparsed = Vec::new();
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        let note = match Note::new_from_line(line) {
+            Ok(note) => note,
+            Err(_) => {
+                unparsed.push(line.to_string());
+                continue;
+            }
+        };
+        match notes.iter_mut().find(|seen| seen.is_copy_of(&note)) {
+            Some(seen) => *seen = newer_copy(seen, &note).clone(),
+            None => notes.push(note),
+        }
+    }
+    (notes, unparsed)
+}
+
+/// Of two copies of the same note, the one changed last, or the first if neither is newer.
+fn newer_copy<'a>(first: &'a Note, second: &'a Note) -> &'a Note {
+    if second.changed_at() > first.changed_at() {
+        second
+    } else {
+        first
+    }
+}
+
+/// Compares two notebooks note by note, matching notes by id and the time they were taken. Older
+/// notes without an id are given one from their timestamp and text, so those match when both are
+/// the same.
+pub fn diff_notes(local: &str, remote: &str) -> NotesDiff {
+    let (local_notes, local_unparsed) = parse_unique_notes(local);
+    let (remote_notes, remote_unparsed) = parse_unique_notes(remote);
+    let only_in = |notes: &Vec<Note>, other: &Vec<Note>| -> Vec<Note> {
+        notes
+            .iter()
+            .filter(|note| !other.iter().any(|other| other.is_copy_of(note)))
+            .cloned()
+            .collect()
+    };
+
+    let changed = local_notes
+        .iter()
+        .filter_map(|local| {
+            remote_notes
+                .iter()
+                .find(|remote| remote.is_copy_of(local) && *remote != local)
+                .map(|remote| (local.clone(), remote.clone()))
+        })
+        .collect();
+    NotesDiff {
+        local_only: only_in(&local_notes, &remote_notes),
+        remote_only: only_in(&remote_notes, &local_notes),
+        changed,
+        local_unparsed,
+        remote_unparsed,
+    }
+}
+
+/// Takes the union of two notebooks in time order. A note on both sides is kept once, as whichever
+/// copy was edited or done last.
+pub fn merge_notes(local: &str, remote: &str) -> NotesMerge {
+    let diff = diff_notes(local, remote);
+
+    let mut updated = 0;
+    let (mut notes, _) = parse_unique_notes(local);
+    for note in notes.iter_mut() {
+        if let Some((_, remote)) = diff
+            .changed
+            .iter()
+            .find(|(local, _)| local.is_copy_of(note))
+        {
+            if newer_copy(note, remote) == remote {
+                *note = remote.clone();
+                updated += 1;
+            }
+        }
+    }
+    notes.extend(diff.remote_only.iter().cloned());
+    notes.sort_by_key(|note| note.timestamp);
+
+    NotesMerge {
+        notes,
+        local_only: diff.local_only.len(),
+        remote_only: diff.remote_only.len(),
+        updated,
+        local_unparsed: diff.local_unparsed,
+        remote_skipped: diff.remote_unparsed.len(),
+    }
+}
+
+fn flatten_settings(prefix: &str, value: &Value, keys: &mut BTreeMap<String, String>) {
+    match value {
+        Value::Mapping(mapping) => {
+            for (key, value) in mapping {
+                let key = match key {
+                    Value::String(key) => key.to_string(),
+                    other => format!("{:?}", other),
+                };
+                let key = if prefix.is_empty() {
+                    key
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten_settings(&key, value, keys);
+            }
+        }
+        Value::Null => {}
+        other => {
+            let value = serde_yaml::to_string(other).unwrap_or_default();
+            keys.insert(prefix.to_string(), value.trim().to_string());
+        }
+    }
+}
+
+/// Lists the settings keys that differ between two settings files as `(key, local, remote)`.
+pub fn diff_settings(local: &str, remote: &str) -> Vec<(String, Option<String>, Option<String>)> {
+    let mut local_keys = BTreeMap::new();
+    let mut remote_keys = BTreeMap::new();
+    flatten_settings(
+        "",
+        &serde_yaml::from_str(local).unwrap_or(Value::Null),
+        &mut local_keys,
+    );
+    flatten_settings(
+        "",
+        &serde_yaml::from_str(remote).unwrap_or(Value::Null),
+        &mut remote_keys,
+    );
+
+    let all_keys: BTreeSet<&String> = local_keys.keys().chain(remote_keys.keys()).collect();
+    all_keys
+        .into_iter()
+        .filter(|key| local_keys.get(*key) != remote_keys.get(*key))
+        .map(|key| {
+            (
+                key.to_string(),
+                local_keys.get(key).cloned(),
+                remote_keys.get(key).cloned(),
+            )
+        })
+        .collect()
 }
 
 pub fn scriber_files_setup() -> bool {
@@ -72,18 +600,17 @@ pub fn scriber_files_setup() -> bool {
 
 #[cfg(test)]
 mod tests {
-    use crate::model::RemoteSettings;
-
     use super::*;
 
     #[test]
     fn test_get_default_settings_none_asked() {
-        let s = get_settings_from_disk(None);
+        let s = get_settings_from_disk(None).unwrap();
         assert_eq!(s, Settings::default())
     }
     #[test]
     fn test_get_default_settings_path_not_exists() {
-        let s = get_settings_from_disk(Some(PathBuf::from("this/path/probably/does/not/exists")));
+        let s = get_settings_from_disk(Some(PathBuf::from("this/path/probably/does/not/exists")))
+            .unwrap();
         assert_eq!(s, Settings::default())
     }
 
@@ -93,12 +620,17 @@ mod tests {
             .join("resources")
             .join("tests")
             .join("settings_empty_remote.yaml");
-        let actual = get_settings_from_disk(Some(resources_dir));
+        let actual = get_settings_from_disk(Some(resources_dir)).unwrap();
         let expected = Settings {
             default_notebook: "my_notes.txt".to_string(),
             verbosity: 0,
             no_magic_commands: true,
-            remote: Some(RemoteSettings { gist_id: None }),
+            remote: Some(RemoteSettings {
+                gist_id: None,
+                token_file: None,
+                api_url: None,
+                oauth_url: None,
+            }),
         };
         assert_eq!(actual, expected)
     }
@@ -109,13 +641,16 @@ mod tests {
             .join("resources")
             .join("tests")
             .join("settings_with_remote.yaml");
-        let actual = get_settings_from_disk(Some(resources_dir));
+        let actual = get_settings_from_disk(Some(resources_dir)).unwrap();
         let expected = Settings {
             default_notebook: "my_notes.txt".to_string(),
             verbosity: 0,
             no_magic_commands: true,
             remote: Some(RemoteSettings {
                 gist_id: Some("tests-gist-id".to_string()),
+                token_file: None,
+                api_url: None,
+                oauth_url: None,
             }),
         };
         assert_eq!(actual, expected)
@@ -126,7 +661,7 @@ mod tests {
             .join("resources")
             .join("tests")
             .join("settings_with_missing.yaml");
-        let actual = get_settings_from_disk(Some(resources_dir));
+        let actual = get_settings_from_disk(Some(resources_dir)).unwrap();
         let expected = Settings {
             default_notebook: "my_notes.txt".to_string(),
             verbosity: 0,
@@ -135,4 +670,266 @@ mod tests {
         };
         assert_eq!(actual, expected)
     }
+
+    #[test]
+    fn test_notebook_names() {
+        assert_eq!(get_notebook_file_name("work").unwrap(), "work.txt");
+        assert_eq!(get_notebook_file_name("work.txt").unwrap(), "work.txt");
+        assert_eq!(
+            get_notebook_name(&get_scribr_home_dir().join("project-x.txt")),
+            "project-x"
+        );
+        for bad_name in ["", ".hidden", "../notes", "my notes"] {
+            assert!(matches!(
+                get_notebook_file_name(bad_name),
+                Err(ScribrError::Config(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_find_and_replace_note() {
+        let content = "Wed, 1 Mar 2023 10:00:00 +0000 [aaaa1111] - first\n\
+                       not a note\n\
+                       Wed, 1 Mar 2023 11:00:00 +0000 [aaaa2222] - second\n\
+                       Wed, 1 Mar 2023 12:00:00 +0000 - legacy\n";
+        let notes: Vec<Note> = content
+            .lines()
+            .filter_map(|line| Note::new_from_line(line).ok())
+            .collect();
+
+        assert_eq!(find_note(&notes, "aaaa2").unwrap().note_value, "second");
+        assert_eq!(find_note(&notes, "aaaa1111").unwrap().note_value, "first");
+        assert!(matches!(
+            find_note(&notes, "aaaa"),
+            Err(ScribrError::NoteNotFound(_))
+        ));
+        assert!(matches!(
+            find_note(&notes, "ffff"),
+            Err(ScribrError::NoteNotFound(_))
+        ));
+
+        let edited = notes[1].edited("second, fixed");
+        let mut content = content.as_bytes().to_vec();
+        content.extend(b"bad \xff byte\n");
+        let rewritten = replace_note(&content, &notes[1], Some(&edited));
+        let rewritten = replace_note(&rewritten, &notes[0], None);
+        assert!(rewritten.ends_with(b"bad \xff byte\n"));
+        let rewritten = String::from_utf8_lossy(&rewritten);
+        let lines: Vec<&str> = rewritten.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "not a note");
+        assert_eq!(Note::new_from_line(lines[1]).unwrap(), edited);
+        assert_eq!(Note::new_from_line(lines[2]).unwrap(), notes[2]);
+        assert!(lines[2].contains(&format!("[{}]", notes[2].id)));
+    }
+
+    #[test]
+    fn test_resolve_link() {
+        let notes = vec![
+            Note::new("Deploy runbook\n1. build"),
+            Note::new("standup"),
+            Note::new("Standup"),
+        ];
+        let target = notes[0].id.clone();
+        assert_eq!(resolve_link(&notes, &target).unwrap(), &notes[0]);
+        assert_eq!(resolve_link(&notes, "deploy RUNBOOK").unwrap(), &notes[0]);
+        assert!(matches!(
+            resolve_link(&notes, "standup"),
+            Err(ScribrError::NoteNotFound(_))
+        ));
+        assert!(matches!(
+            resolve_link(&notes, "missing"),
+            Err(ScribrError::NoteNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_due() {
+        // a wednesday
+        let today = NaiveDate::from_ymd_opt(2026, 10, 21).unwrap();
+        let day = |d| NaiveDate::from_ymd_opt(2026, 10, d).unwrap();
+        assert_eq!(
+            parse_due("2026-11-02", today).unwrap(),
+            NaiveDate::from_ymd_opt(2026, 11, 2).unwrap()
+        );
+        assert_eq!(parse_due("today", today).unwrap(), today);
+        assert_eq!(parse_due("Tomorrow", today).unwrap(), day(22));
+        assert_eq!(parse_due("friday", today).unwrap(), day(23));
+        assert_eq!(parse_due("wed", today).unwrap(), today);
+        assert_eq!(parse_due("mon", today).unwrap(), day(26));
+        assert!(matches!(
+            parse_due("someday", today),
+            Err(ScribrError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_timers() {
+        let start = |line: &str| Note::new_from_line(line).unwrap();
+        let review =
+            start("Mon, 19 Oct 2026 09:00:00 +0000 [aaaa0001 start] - reviewing PR #42 for #api");
+        let standup = start("Mon, 19 Oct 2026 10:30:00 +0000 [aaaa0002 start] - standup");
+        let mut stop_review = Note::new_timer_stop(&review, "1h 30m");
+        stop_review.timestamp = standup.timestamp;
+        let notes = vec![review, stop_review, standup.clone()];
+
+        let spans = pair_timers(&notes);
+        assert_eq!(spans.len(), 2);
+        let now = standup.timestamp + Duration::minutes(15);
+        assert_eq!(format_duration(spans[0].duration(now)), "1h 30m");
+        assert!(spans[1].stopped_at.is_none());
+        assert_eq!(format_duration(spans[1].duration(now)), "0h 15m");
+
+        let totals: Vec<(String, String)> = total_time_by_tag(&spans, now, "untagged")
+            .into_iter()
+            .map(|(tag, total)| (tag, format_duration(total)))
+            .collect();
+        assert_eq!(
+            totals,
+            vec![
+                ("api".to_string(), "1h 30m".to_string()),
+                ("untagged".to_string(), "0h 15m".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strip_editor_header() {
+        let content = format!(
+            "# Taking a note at 10:00\n# Notebook: notes.txt\n{}\n\nfirst line\n#release went fine\n\n",
+            EDITOR_SCISSORS_LINE
+        );
+        assert_eq!(
+            strip_editor_header(&content),
+            "first line\n#release went fine"
+        );
+        assert_eq!(
+            strip_editor_header(&format!("# Amend\n{}\n\n", EDITOR_SCISSORS_LINE)),
+            ""
+        );
+        // without the scissors line nothing is taken as the header
+        assert_eq!(
+            strip_editor_header("#standup notes\n  second line\n"),
+            "#standup notes\n  second line"
+        );
+    }
+
+    #[test]
+    fn test_merge_notes() {
+        let local = "Wed, 1 Mar 2023 10:00:00 +0000 - first\n\
+                     Wed, 1 Mar 2023 12:00:00 +0000 - only on this laptop\n";
+        let remote = "Wed, 1 Mar 2023 10:00:00 +0000 - first\n\
+                      Wed, 1 Mar 2023 11:00:00 +0000 - only on the other laptop\n\
+                      Wed, 1 Mar 2023 11:00:00 +0000 - only on the other laptop\n";
+        let merge = merge_notes(local, remote);
+        let values: Vec<&str> = merge
+            .notes
+            .iter()
+            .map(|note| note.note_value.as_str())
+            .collect();
+        assert_eq!(
+            values,
+            vec!["first", "only on the other laptop", "only on this laptop"]
+        );
+        assert_eq!(merge.local_only, 1);
+        assert_eq!(merge.remote_only, 1);
+    }
+
+    #[test]
+    fn test_colliding_ids() {
+        let local = "Wed, 1 Mar 2023 10:00:00 +0000 [cafe0001] - taken here\n";
+        let remote = "Thu, 2 Mar 2023 10:00:00 +0000 [cafe0001] - taken there\n";
+        let merge = merge_notes(local, remote);
+        assert_eq!(merge.notes.len(), 2);
+        assert_eq!(
+            (merge.local_only, merge.remote_only, merge.updated),
+            (1, 1, 0)
+        );
+
+        let content = merge.to_file_content();
+        let rewritten = replace_note(content.as_bytes(), &merge.notes[0], None);
+        let rewritten = String::from_utf8(rewritten).unwrap();
+        assert_eq!(rewritten, format!("{}\n", merge.notes[1]));
+    }
+
+    #[test]
+    fn test_merge_notes_with_unparsed_lines() {
+        let local = "Wed, 1 Mar 2023 10:00:00 +0000 - first\n\
+                     <<<<<<< HEAD\n";
+        let remote = "Wed, 1 Mar 2023 11:00:00 +0000 - second\n\
+                      a hand edited line\n";
+        let merge = merge_notes(local, remote);
+        assert_eq!(merge.notes.len(), 2);
+        assert_eq!(merge.remote_skipped, 1);
+        assert_eq!(
+            merge.to_file_content(),
+            format!("{}\n{}\n<<<<<<< HEAD\n", merge.notes[0], merge.notes[1])
+        );
+    }
+
+    #[test]
+    fn test_merge_changed_notes() {
+        let local = "Wed, 1 Mar 2023 10:00:00 +0000 [aaaa1111 todo] - book the #offsite\n\
+                     Wed, 1 Mar 2023 11:00:00 +0000 [bbbb2222 edited=2023-03-03T09:00:00+00:00] - edited here last\n";
+        let remote = "Wed, 1 Mar 2023 10:00:00 +0000 [aaaa1111 done=2023-03-02T09:00:00+00:00 todo] - book the #offsite\n\
+                      Wed, 1 Mar 2023 11:00:00 +0000 [bbbb2222 edited=2023-03-02T09:00:00+00:00] - edited there first\n";
+
+        let diff = diff_notes(local, remote);
+        assert!(diff.local_only.is_empty() && diff.remote_only.is_empty());
+        assert_eq!(diff.changed.len(), 2);
+
+        let merge = merge_notes(local, remote);
+        assert_eq!(merge.notes.len(), 2);
+        assert_eq!(merge.updated, 1);
+        assert!(!merge.notes[0].is_open_todo());
+        assert_eq!(merge.notes[1].note_value, "edited here last");
+
+        // merging the other way round settles on the same notes
+        let merge = merge_notes(remote, local);
+        assert_eq!(merge.updated, 1);
+        assert!(!merge.notes[0].is_open_todo());
+        assert_eq!(merge.notes[1].note_value, "edited here last");
+    }
+
+    #[test]
+    fn test_check_notebook() {
+        let mut content = b"Wed, 1 Mar 2023 10:00:00 +0000 - first\n\
+                            Wed, 1 Mar 2023 12:00:00 +0000 - third\n\
+                            <<<<<<< HEAD\n\
+                            Wed, 1 Mar 2023 11:00:00 +0000 - second\n\
+                            Wed, 1 Mar 2023 10:00:00 +0000 - first\n"
+            .to_vec();
+        content.extend_from_slice(b"Wed, 1 Mar 2023 13:00:00 +0000 - bad \xff\n");
+
+        let check = check_notebook(&content);
+        let bad_line_numbers: Vec<usize> = check.bad_lines.iter().map(|bad| bad.0).collect();
+        assert_eq!(bad_line_numbers, vec![3, 6]);
+        assert_eq!(check.out_of_order, vec![4]);
+        assert_eq!(check.duplicates, vec![5]);
+        assert_eq!(check.problem_count(), 4);
+        let fixed: Vec<String> = check_notebook(check.fixed_content().as_bytes())
+            .notes
+            .into_iter()
+            .map(|note| note.note_value)
+            .collect();
+        assert_eq!(fixed, vec!["first", "second", "third"]);
+    }
+
+    #[test]
+    fn test_diff_settings() {
+        let local = "default_notebook: my_notes.txt\nverbosity: 1\nremote:\n  gist_id: abc\n";
+        let remote = "default_notebook: my_notes.txt\nremote:\n  gist_id: def\n";
+        assert_eq!(
+            diff_settings(local, remote),
+            vec![
+                (
+                    "remote.gist_id".to_string(),
+                    Some("abc".to_string()),
+                    Some("def".to_string())
+                ),
+                ("verbosity".to_string(), Some("1".to_string()), None),
+            ]
+        );
+    }
 }
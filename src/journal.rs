@@ -0,0 +1,201 @@
+use std::fs::{copy, create_dir_all, read_dir, remove_dir_all, remove_file, write, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ScribrError, ScribrResult};
+use crate::internal::get_scribr_home_dir;
+
+pub const SCRIBR_HISTORY_DIR_NAME: &str = ".history";
+/// Operations kept in the journal, the oldest are dropped after this.
+const JOURNAL_MAX_ENTRIES: usize = 50;
+const JOURNAL_ENTRY_FILE_NAME: &str = "entry.yaml";
+
+/// One operation in the journal. Each entry is a dir under `.history` holding this as yaml, along
+/// with a copy of every file the operation was about to change.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub operation: String,
+    /// When the operation ran, as rfc3339.
+    pub taken_at: String,
+    pub files: Vec<JournalFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JournalFile {
+    /// Path of the file relative to the scribr dir.
+    pub name: String,
+    /// Whether the file was there before, so undo knows to delete it if not.
+    pub existed: bool,
+}
+
+impl JournalEntry {
+    pub fn taken_at(&self) -> Option<DateTime<Local>> {
+        DateTime::parse_from_rfc3339(&self.taken_at)
+            .ok()
+            .map(|taken_at| taken_at.with_timezone(&Local))
+    }
+}
+
+pub fn get_history_dir() -> PathBuf {
+    get_scribr_home_dir().join(SCRIBR_HISTORY_DIR_NAME)
+}
+
+/// Snapshots `paths` before `operation` writes to them, so it can be undone.
+pub fn record<P: AsRef<Path>>(operation: &str, paths: &[P]) -> ScribrResult<()> {
+    record_in(&get_scribr_home_dir(), operation, paths)
+}
+
+/// Lists the journal, newest operation first.
+pub fn list_entries() -> ScribrResult<Vec<JournalEntry>> {
+    Ok(list_entry_dirs(&get_history_dir())?
+        .into_iter()
+        .map(|(_, entry)| entry)
+        .collect())
+}
+
+/// Puts back the files from the newest operation in the journal and drops it from the journal.
+pub fn undo_last() -> ScribrResult<Option<JournalEntry>> {
+    undo_last_in(&get_scribr_home_dir())
+}
+
+fn record_in<P: AsRef<Path>>(home_dir: &Path, operation: &str, paths: &[P]) -> ScribrResult<()> {
+    let taken_at = Local::now();
+    let history_dir = home_dir.join(SCRIBR_HISTORY_DIR_NAME);
+    let entry_dir = history_dir.join(taken_at.format("%Y%m%dT%H%M%S%.9f").to_string());
+    let err_msg = format!(
+        "Could not write to the journal in {}",
+        history_dir.display()
+    );
+    create_dir_all(&entry_dir).map_err(|e| ScribrError::io(&err_msg, e))?;
+
+    let mut files = Vec::new();
+    for (i, path) in paths.iter().enumerate() {
+        let path = path.as_ref();
+        let name = path
+            .strip_prefix(home_dir)
+            .map_err(|_| {
+                ScribrError::Config(format!(
+                    "{} is outside the scribr dir and can't be journaled",
+                    path.display()
+                ))
+            })?
+            .to_string_lossy()
+            .to_string();
+        let existed = path.is_file();
+        if existed {
+            copy(path, entry_dir.join(i.to_string())).map_err(|e| ScribrError::io(&err_msg, e))?;
+        }
+        files.push(JournalFile { name, existed });
+    }
+    let entry = JournalEntry {
+        operation: operation.to_string(),
+        taken_at: taken_at.to_rfc3339(),
+        files,
+    };
+    write(
+        entry_dir.join(JOURNAL_ENTRY_FILE_NAME),
+        serde_yaml::to_string(&entry)?,
+    )
+    .map_err(|e| ScribrError::io(&err_msg, e))?;
+
+    for (old_entry_dir, _) in list_entry_dirs(&history_dir)?
+        .into_iter()
+        .skip(JOURNAL_MAX_ENTRIES)
+    {
+        remove_dir_all(&old_entry_dir).map_err(|e| ScribrError::io(&err_msg, e))?;
+    }
+    Ok(())
+}
+
+fn undo_last_in(home_dir: &Path) -> ScribrResult<Option<JournalEntry>> {
+    let history_dir = home_dir.join(SCRIBR_HISTORY_DIR_NAME);
+    let (entry_dir, entry) = match list_entry_dirs(&history_dir)?.into_iter().next() {
+        Some(newest) => newest,
+        None => return Ok(None),
+    };
+
+    for (i, file) in entry.files.iter().enumerate() {
+        let path = home_dir.join(&file.name);
+        let err_msg = format!("Could not put back {}", path.display());
+        if file.existed {
+            copy(entry_dir.join(i.to_string()), &path).map_err(|e| ScribrError::io(&err_msg, e))?;
+        } else if path.exists() {
+            remove_file(&path).map_err(|e| ScribrError::io(&err_msg, e))?;
+        }
+    }
+    remove_dir_all(&entry_dir).map_err(|e| {
+        ScribrError::io(
+            format!("Could not remove {} from the journal", entry_dir.display()),
+            e,
+        )
+    })?;
+    Ok(Some(entry))
+}
+
+/// Entry dirs in the journal, newest first. Dirs without a readable entry are left out.
+fn list_entry_dirs(history_dir: &Path) -> ScribrResult<Vec<(PathBuf, JournalEntry)>> {
+    if !history_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let err_msg = format!("Could not read the journal in {}", history_dir.display());
+    let mut entry_dirs: Vec<PathBuf> = read_dir(history_dir)
+        .map_err(|e| ScribrError::io(&err_msg, e))?
+        .flatten()
+        .map(|dir_entry| dir_entry.path())
+        .filter(|path| path.is_dir())
+        .collect();
+    // the dir names are timestamps, so they sort by age
+    entry_dirs.sort();
+    entry_dirs.reverse();
+
+    let mut entries = Vec::new();
+    for entry_dir in entry_dirs {
+        let entry_file = match File::open(entry_dir.join(JOURNAL_ENTRY_FILE_NAME)) {
+            Ok(entry_file) => entry_file,
+            Err(_) => continue,
+        };
+        if let Ok(entry) = serde_yaml::from_reader(BufReader::new(entry_file)) {
+            entries.push((entry_dir, entry));
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+    use std::fs::read_to_string;
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn test_record_and_undo() {
+        let home_dir = temp_dir().join(format!("scribr-journal-test-{}", process::id()));
+        let _ = remove_dir_all(&home_dir);
+        create_dir_all(&home_dir).unwrap();
+        let notebook = home_dir.join("notes.txt");
+        let new_notebook = home_dir.join("work.txt");
+        write(&notebook, "before\n").unwrap();
+
+        record_in(&home_dir, "first", &[&notebook]).unwrap();
+        write(&notebook, "after first\n").unwrap();
+        record_in(&home_dir, "second", &[&notebook, &new_notebook]).unwrap();
+        write(&notebook, "after second\n").unwrap();
+        write(&new_notebook, "new\n").unwrap();
+
+        let undone = undo_last_in(&home_dir).unwrap().unwrap();
+        assert_eq!(undone.operation, "second");
+        assert_eq!(read_to_string(&notebook).unwrap(), "after first\n");
+        assert!(!new_notebook.exists());
+
+        assert_eq!(undo_last_in(&home_dir).unwrap().unwrap().operation, "first");
+        assert_eq!(read_to_string(&notebook).unwrap(), "before\n");
+        assert!(undo_last_in(&home_dir).unwrap().is_none());
+
+        remove_dir_all(&home_dir).unwrap();
+    }
+}
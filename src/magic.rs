@@ -0,0 +1,254 @@
+use chrono::Local;
+
+use crate::error::ScribrResult;
+use crate::internal::parse_due;
+use crate::model::{Note, Settings};
+
+/// A magic command looks for a bit of syntax in note text, like `:tada:`, and acts on it when the
+/// note is taken or shown. Add new ones to `MagicEngine::new`.
+pub trait MagicCommand {
+    /// Runs on a new note before it is written, and may change its text or attributes. An error
+    /// stops the note being taken.
+    fn on_take(&self, _note: &mut Note) -> ScribrResult<()> {
+        Ok(())
+    }
+
+    /// Runs on the text of a note before it is printed. Never changes what is stored.
+    fn on_display(&self, note_value: String) -> String {
+        note_value
+    }
+}
+
+/// Runs every magic command over notes, or none of them if magic commands are turned off.
+pub struct MagicEngine {
+    commands: Vec<Box<dyn MagicCommand>>,
+}
+
+impl MagicEngine {
+    pub fn new(settings: &Settings) -> MagicEngine {
+        if settings.no_magic_commands {
+            return MagicEngine {
+                commands: Vec::new(),
+            };
+        }
+        MagicEngine {
+            commands: vec![
+                Box::new(TodoPrefix),
+                Box::new(DueMarker),
+                Box::new(EmojiShortcodes),
+            ],
+        }
+    }
+
+    pub fn on_take(&self, note: &mut Note) -> ScribrResult<()> {
+        for command in &self.commands {
+            command.on_take(note)?;
+        }
+        Ok(())
+    }
+
+    /// Copy of the note with its text as it should be shown.
+    pub fn for_display(&self, note: &Note) -> Note {
+        let mut note_value = note.note_value.clone();
+        for command in &self.commands {
+            note_value = command.on_display(note_value);
+        }
+        Note {
+            note_value,
+            ..note.clone()
+        }
+    }
+}
+
+/// Makes a note starting with `!todo` a todo, dropping the prefix from its text.
+struct TodoPrefix;
+
+const TODO_PREFIX: &str = "!todo";
+
+impl MagicCommand for TodoPrefix {
+    fn on_take(&self, note: &mut Note) -> ScribrResult<()> {
+        let rest = match note.note_value.get(..TODO_PREFIX.len()) {
+            Some(prefix) if prefix.eq_ignore_ascii_case(TODO_PREFIX) => {
+                &note.note_value[TODO_PREFIX.len()..]
+            }
+            _ => return Ok(()),
+        };
+        if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+            return Ok(());
+        }
+        let note_value = rest.trim_start().to_string();
+        note.set_note_value(&note_value);
+        note.mark_todo();
+        Ok(())
+    }
+}
+
+/// Gives a note the due date in a `!due(friday)` anywhere in it, dropping that from its text.
+struct DueMarker;
+
+const DUE_MARKER_START: &str = "!due(";
+
+impl MagicCommand for DueMarker {
+    fn on_take(&self, note: &mut Note) -> ScribrResult<()> {
+        // lowercasing can change byte lengths, so match on the text as it is
+        let start = match note.note_value.char_indices().find_map(|(i, _)| {
+            note.note_value
+                .get(i..i + DUE_MARKER_START.len())
+                .filter(|marker| marker.eq_ignore_ascii_case(DUE_MARKER_START))
+                .map(|_| i)
+        }) {
+            Some(start) => start,
+            None => return Ok(()),
+        };
+        let spec_start = start + DUE_MARKER_START.len();
+        let spec_end = match note.note_value[spec_start..].find(')') {
+            Some(len) => spec_start + len,
+            None => return Ok(()),
+        };
+        let due = parse_due(
+            &note.note_value[spec_start..spec_end],
+            Local::now().date_naive(),
+        )?;
+
+        let before = note.note_value[..start].trim_end();
+        let after = note.note_value[spec_end + 1..].trim_start();
+        let separator = if before.is_empty() || after.is_empty() {
+            ""
+        } else {
+            " "
+        };
+        let note_value = format!("{}{}{}", before, separator, after);
+        note.set_note_value(&note_value);
+        note.set_due(due);
+        Ok(())
+    }
+}
+
+/// Shows `:shortcode:` as the emoji it names, leaving any it doesn't know alone.
+struct EmojiShortcodes;
+
+const EMOJI_SHORTCODES: [(&str, &str); 24] = [
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("100", "💯"),
+    ("bug", "🐛"),
+    ("bulb", "💡"),
+    ("coffee", "☕"),
+    ("construction", "🚧"),
+    ("eyes", "👀"),
+    ("fire", "🔥"),
+    ("heart", "❤️"),
+    ("memo", "📝"),
+    ("question", "❓"),
+    ("rocket", "🚀"),
+    ("smile", "😄"),
+    ("sparkles", "✨"),
+    ("tada", "🎉"),
+    ("thinking", "🤔"),
+    ("thumbsdown", "👎"),
+    ("thumbsup", "👍"),
+    ("warning", "⚠️"),
+    ("wave", "👋"),
+    ("white_check_mark", "✅"),
+    ("x", "❌"),
+    ("zap", "⚡"),
+];
+
+fn is_shortcode_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || ['_', '+', '-'].contains(&c)
+}
+
+impl MagicCommand for EmojiShortcodes {
+    fn on_display(&self, note_value: String) -> String {
+        let mut displayed = String::with_capacity(note_value.len());
+        let mut rest = note_value.as_str();
+        while let Some(start) = rest.find(':') {
+            displayed.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            let emoji = after.find(':').and_then(|end| {
+                let code = &after[..end];
+                if code.is_empty() || !code.chars().all(is_shortcode_char) {
+                    return None;
+                }
+                EMOJI_SHORTCODES
+                    .iter()
+                    .find(|(shortcode, _)| *shortcode == code)
+                    .map(|(_, emoji)| (*emoji, end))
+            });
+            match emoji {
+                Some((emoji, end)) => {
+                    displayed.push_str(emoji);
+                    rest = &after[end + 1..];
+                }
+                None => {
+                    displayed.push(':');
+                    rest = after;
+                }
+            }
+        }
+        displayed.push_str(rest);
+        displayed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[test]
+    fn test_todo_prefix() {
+        let engine = MagicEngine::new(&Settings::default());
+        let mut note = Note::new("!TODO  book the #offsite");
+        engine.on_take(&mut note).unwrap();
+        assert!(note.is_open_todo());
+        assert_eq!(note.note_value, "book the #offsite");
+        assert_eq!(note.tags, vec!["offsite"]);
+
+        let mut note = Note::new("!todos are great");
+        engine.on_take(&mut note).unwrap();
+        assert!(!note.is_todo());
+    }
+
+    #[test]
+    fn test_due_marker() {
+        let engine = MagicEngine::new(&Settings::default());
+        let mut note = Note::new("renew the cert !DUE(2026-11-02) before it lapses");
+        engine.on_take(&mut note).unwrap();
+        assert_eq!(note.note_value, "renew the cert before it lapses");
+        assert_eq!(note.due(), NaiveDate::from_ymd_opt(2026, 11, 2));
+        assert!(note.is_open_todo());
+
+        let mut note = Note::new("!due(someday) maybe");
+        assert!(engine.on_take(&mut note).is_err());
+
+        let mut note = Note::new("Ⱥ trip !Due(2026-11-02)");
+        engine.on_take(&mut note).unwrap();
+        assert_eq!(note.note_value, "Ⱥ trip");
+        assert_eq!(note.due(), NaiveDate::from_ymd_opt(2026, 11, 2));
+
+        let mut note = Note::new("İ!due(日)");
+        assert!(engine.on_take(&mut note).is_err());
+        assert_eq!(note.note_value, "İ!due(日)");
+    }
+
+    #[test]
+    fn test_emoji_shortcodes() {
+        let note = Note::new("shipped it :tada: at 10:30, :nope: :bug::fire:");
+        let settings = Settings::default();
+        assert_eq!(
+            MagicEngine::new(&settings).for_display(&note).note_value,
+            "shipped it 🎉 at 10:30, :nope: 🐛🔥"
+        );
+
+        let settings = Settings {
+            no_magic_commands: true,
+            ..Settings::default()
+        };
+        assert_eq!(
+            MagicEngine::new(&settings).for_display(&note).note_value,
+            note.note_value
+        );
+    }
+}
@@ -1,15 +1,29 @@
 extern crate core;
 
+use std::path::PathBuf;
+use std::process::exit;
+
 use clap::{Parser, Subcommand};
 
 use crate::commands::{
-    backup_notes, echo_under_construction, init, list_notes, open_path, restore_notes,
-    search_notes, take_note,
+    amend_note, backup_notes, check_notebooks, complete_todo, delete_notebook, diff_remote,
+    edit_note, init, lint_notebooks, list_history, list_notebooks, list_notes, list_people,
+    list_remote_gists, list_remote_history, list_tags, list_todos, login, login_status, logout,
+    new_notebook, open_path, remove_note, rename_notebook, restore_notes, search_notes,
+    show_agenda, show_note, show_timesheet, start_timer, stop_timer, switch_notebook, take_note,
+    undo,
+};
+use crate::error::{ScribrError, ScribrResult};
+use crate::internal::{
+    get_notebook_file_name, get_scribr_config_file, get_settings_from_disk, scriber_files_setup,
 };
-use crate::internal::{get_scribr_config_file, get_settings_from_disk, scriber_files_setup};
+use crate::model::Settings;
 
 mod commands;
+mod error;
 mod internal;
+mod journal;
+mod magic;
 mod model;
 
 // https://docs.rs/clap/4.1.8/clap/_derive/index.html
@@ -25,10 +39,14 @@ struct Cli {
     #[arg(short, long, action = clap::ArgAction::Count)]
     verbose: u8,
 
-    /// Turn magic all off while searching or listing notes
-    #[arg(long)]
+    /// Turn magic commands off while taking, searching or listing notes
+    #[arg(long, global = true)]
     no_magic_commands: bool,
 
+    /// Use this notebook instead of the default one
+    #[arg(short = 'b', long, global = true)]
+    notebook: Option<String>,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -39,12 +57,25 @@ const PRINT_LEN_DEFAULT: &str = "20";
 enum Commands {
     /// Take a note
     Take {
-        /// The note value the note should contain.
-        note: String,
+        /// The note value the note should contain. Leave it out to write the note in $EDITOR, or
+        /// pass - to read it from stdin.
+        note: Option<String>,
 
         /// Echo the note to the console rather than write to disk
         #[arg(short, long)]
         echo: bool,
+
+        /// Ask what you did today and confirm the note before it's saved
+        #[arg(short, long, conflicts_with = "note")]
+        interactive: bool,
+
+        /// Make the note a todo, the same as starting it with !todo
+        #[arg(short, long)]
+        todo: bool,
+
+        /// Make the note a todo due on a day, YYYY-MM-DD, today, tomorrow or a weekday
+        #[arg(short, long)]
+        due: Option<String>,
     },
 
     /// 📑 List your notes chronologically.
@@ -52,6 +83,18 @@ enum Commands {
         /// Number of notes to list
         #[arg(short = 'n', long, default_value = PRINT_LEN_DEFAULT)]
         count: u8,
+
+        /// Fail on the first malformed line instead of flagging it
+        #[arg(long)]
+        strict: bool,
+
+        /// Only show notes with this tag, repeat it for more tags
+        #[arg(short, long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+
+        /// Show notes with any of the tags rather than all of them
+        #[arg(long, requires = "tags")]
+        any_tag: bool,
     },
 
     /// 🔎 Search your notes with fuzzy matching
@@ -62,11 +105,137 @@ enum Commands {
         /// Number of notes to list.
         #[arg(short = 'n', long, default_value = PRINT_LEN_DEFAULT)]
         count: u8,
+
+        /// Fail on the first malformed line instead of skipping it
+        #[arg(long)]
+        strict: bool,
+
+        /// Search every notebook rather than just the one in use
+        #[arg(short, long)]
+        all: bool,
+
+        /// Only show notes with this tag, repeat it for more tags
+        #[arg(short, long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+
+        /// Show notes with any of the tags rather than all of them
+        #[arg(long, requires = "tags")]
+        any_tag: bool,
+    },
+
+    /// ☐ List open todos across all your notebooks
+    Todo,
+
+    /// ▶️ Start a timer on what you're doing, stopping any timer already running
+    Start {
+        /// What you're doing, #tags are used to add up time in the timesheet
+        note: String,
+    },
+
+    /// ⏹️ Stop the running timer
+    Stop,
+
+    /// ⏱️ Add up the time from your timers by #tag
+    Timesheet {
+        /// Only count timers started this week, from Monday
+        #[arg(short, long)]
+        week: bool,
+
+        /// Print CSV rather than a table
+        #[arg(long)]
+        csv: bool,
+    },
+
+    /// 📅 Show overdue todos, then the ones due today and later, by day
+    Agenda {
+        /// Print nothing, and exit with an error if anything is overdue
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// ✅ Mark a todo as done
+    Done {
+        /// Id of the todo, or enough of the start of it to tell it apart
+        id: String,
+    },
+
+    /// 🏷️ List every #tag in your notes with how often and when it was last used
+    Tags,
+
+    /// 👥 List everyone @mentioned in your notes, or every note mentioning someone
+    People {
+        /// Show the notes mentioning this person, oldest first
+        name: Option<String>,
+    },
+
+    /// Show a note in full, with the notes it links to and the notes linking back to it
+    Show {
+        /// Id of the note, or enough of the start of it to tell it apart
+        id: String,
+    },
+
+    /// Change the text of a note, keeping its id and timestamp
+    Edit {
+        /// Id of the note, or enough of the start of it to tell it apart
+        id: String,
+
+        /// The new note value, leave it out to edit the note in $EDITOR
+        note: Option<String>,
+
+        /// Don't ask before saving the change
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Change the last note you took
+    Amend {
+        /// The new note value, leave it out to edit the note in $EDITOR
+        note: Option<String>,
+
+        /// Don't ask before saving the change
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Remove a note
+    Rm {
+        /// Id of the note, or enough of the start of it to tell it apart
+        id: String,
+
+        /// Don't ask before removing the note
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// ↩️ Undo the last change scribr made to your notes or settings
+    Undo,
+
+    /// 🕰️ List the recent changes that can be undone, newest first
+    History {
+        /// Number of changes to list
+        #[arg(short = 'n', long, default_value = PRINT_LEN_DEFAULT)]
+        count: u8,
     },
 
     /// 📁 Open the notes dir
     Open,
 
+    /// 🔗 Check every [[link]] in your notebooks points at a note
+    Lint,
+
+    /// 🩺 Check your notebooks for bad lines, out of order notes and duplicates
+    Fsck {
+        /// Move bad lines to a .rejects file, sort notes and drop duplicates, after backing up
+        #[arg(long)]
+        fix: bool,
+    },
+
+    /// 📚 Manage your notebooks
+    Notebook {
+        #[command(subcommand)]
+        command: NotebookCommand,
+    },
+
     /// Init or re-init scribr
     Init {
         /// initialize with no backup to github
@@ -83,11 +252,39 @@ enum Commands {
     /// ☁️ Interact with the GitHub in the context of scribr
     #[command()]
     Gh {
+        /// Read a GitHub personal access token from this file instead of the saved login
+        #[arg(long, global = true)]
+        token_file: Option<PathBuf>,
+
         #[command(subcommand)]
         command: Option<GhCommand>,
     },
 }
 
+#[derive(Subcommand)]
+enum NotebookCommand {
+    /// Create an empty notebook
+    New { name: String },
+
+    /// List your notebooks, the default one is marked with a *
+    List,
+
+    /// Make a notebook the default one
+    Switch { name: String },
+
+    /// Rename a notebook
+    Rename { name: String, new_name: String },
+
+    /// Delete a notebook and all of its notes
+    Delete {
+        name: String,
+
+        /// Don't ask before deleting
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
+
 #[derive(Subcommand)]
 enum GhCommand {
     Init {
@@ -100,6 +297,10 @@ enum GhCommand {
         /// include the settings file in your backup
         #[arg(long)]
         include_settings: bool,
+
+        /// Show what would change on the gist without pushing anything
+        #[arg(long)]
+        dry_run: bool,
     },
 
     /// Restore your notes file from a GitHub gist
@@ -108,44 +309,169 @@ enum GhCommand {
         #[arg(short, long)]
         force: bool,
 
+        /// Merge the notes in the remote file into your local file instead of overwriting it
+        #[arg(short, long, conflicts_with = "force")]
+        merge: bool,
+
         /// include the settings file in your restore
         #[arg(long)]
         include_settings: bool,
+
+        /// Show what would change locally without writing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Restore from an older gist revision, see scribr gh history
+        #[arg(long)]
+        revision: Option<String>,
     },
+
+    /// List the revisions of your notes gist
+    History,
+
+    /// List every gist that holds scribr notes
+    List,
+
+    /// Compare your local notes and settings with the gist
+    Diff,
+
+    /// Log in to GitHub and save the access token for future backups
+    Login,
+
+    /// Remove the saved GitHub access token
+    Logout,
+
+    /// Show whether a GitHub access token is saved and who it belongs to
+    Status,
 }
 
 fn main() {
     let cli = Cli::parse();
 
-    if !scriber_files_setup() {
-        println!("Scribr is not initialized on the machine! run scribr init");
-        return;
+    if let Err(err) = run(cli) {
+        // agenda --check is for shell prompts, so it only answers with its exit code
+        if !matches!(err, ScribrError::Overdue(_)) {
+            eprintln!("scribr: {}", err);
+        }
+        exit(err.exit_code());
     }
+}
 
-    let run_settings = get_settings_from_disk(Some(get_scribr_config_file()));
+fn run(cli: Cli) -> ScribrResult<()> {
+    let mut run_settings = if scriber_files_setup() {
+        get_settings_from_disk(Some(get_scribr_config_file()))?
+    } else if let Some(Commands::Init { .. }) = cli.command {
+        Settings::default()
+    } else {
+        return Err(ScribrError::Config(
+            "Scribr is not initialized on the machine! run scribr init".to_string(),
+        ));
+    };
+    if cli.verbose > 0 {
+        run_settings.verbosity = cli.verbose;
+    }
+    if cli.no_magic_commands {
+        run_settings.no_magic_commands = true;
+    }
+    if let Some(notebook) = &cli.notebook {
+        run_settings.default_notebook = get_notebook_file_name(notebook)?;
+        let creating = matches!(
+            cli.command,
+            Some(Commands::Init { .. }) | Some(Commands::Notebook { .. })
+        );
+        if !creating && !run_settings.get_default_notebook_path().is_file() {
+            return Err(ScribrError::Config(format!(
+                "no notebook named {} - create it with scribr notebook new {}",
+                notebook, notebook
+            )));
+        }
+    }
+    run_settings.print_to_console();
 
     // You can check for the existence of subcommands, and if found use their
     // matches just as you would the top level cmd
     match &cli.command {
-        Some(Commands::Take { note, echo }) => take_note(run_settings, note, echo),
-        Some(Commands::List { count }) => list_notes(run_settings, count),
-        Some(Commands::Search { term, count }) => search_notes(run_settings, term, count),
-        Some(Commands::Open {}) => open_path(),
+        Some(Commands::Take {
+            note,
+            echo,
+            interactive,
+            todo,
+            due,
+        }) => take_note(run_settings, note, echo, interactive, todo, due),
+        Some(Commands::List {
+            count,
+            strict,
+            tags,
+            any_tag,
+        }) => list_notes(run_settings, count, strict, tags, any_tag),
+        Some(Commands::Search {
+            term,
+            count,
+            strict,
+            all,
+            tags,
+            any_tag,
+        }) => search_notes(run_settings, term, count, strict, all, tags, any_tag),
+        Some(Commands::Tags) => list_tags(run_settings),
+        Some(Commands::Todo) => list_todos(run_settings),
+        Some(Commands::Start { note }) => start_timer(run_settings, note),
+        Some(Commands::Stop) => stop_timer(run_settings),
+        Some(Commands::Timesheet { week, csv }) => show_timesheet(run_settings, week, csv),
+        Some(Commands::Agenda { check }) => show_agenda(run_settings, check),
+        Some(Commands::Done { id }) => complete_todo(run_settings, id),
+        Some(Commands::People { name }) => list_people(run_settings, name),
+        Some(Commands::Show { id }) => show_note(run_settings, id),
+        Some(Commands::Edit { id, note, yes }) => edit_note(run_settings, id, note, yes),
+        Some(Commands::Amend { note, yes }) => amend_note(run_settings, note, yes),
+        Some(Commands::Rm { id, yes }) => remove_note(run_settings, id, yes),
+        Some(Commands::Undo) => undo(),
+        Some(Commands::History { count }) => list_history(count),
+        Some(Commands::Open) => open_path(),
+        Some(Commands::Fsck { fix }) => check_notebooks(run_settings, fix),
+        Some(Commands::Lint) => lint_notebooks(run_settings),
+        Some(Commands::Notebook { command }) => match command {
+            NotebookCommand::New { name } => new_notebook(name),
+            NotebookCommand::List => list_notebooks(run_settings),
+            NotebookCommand::Switch { name } => switch_notebook(name),
+            NotebookCommand::Rename { name, new_name } => rename_notebook(name, new_name),
+            NotebookCommand::Delete { name, yes } => delete_notebook(name, yes),
+        },
         Some(Commands::Init {
             no_gh,
             force,
             gist_id,
-        }) => init(no_gh, force, &gist_id.as_deref()),
-        Some(Commands::Gh { command }) => match command {
-            Some(GhCommand::Backup { include_settings }) => {
-                backup_notes(run_settings, include_settings)
-            }
+        }) => init(run_settings, no_gh, force, &gist_id.as_deref()),
+        Some(Commands::Gh {
+            command,
+            token_file,
+        }) => match command {
+            Some(GhCommand::Backup {
+                include_settings,
+                dry_run,
+            }) => backup_notes(run_settings, include_settings, dry_run, token_file),
             Some(GhCommand::Restore {
                 force,
+                merge,
+                include_settings,
+                dry_run,
+                revision,
+            }) => restore_notes(
+                run_settings,
+                force,
+                merge,
                 include_settings,
-            }) => restore_notes(run_settings, force, include_settings),
-            _ => {}
+                dry_run,
+                revision,
+                token_file,
+            ),
+            Some(GhCommand::History) => list_remote_history(run_settings, token_file),
+            Some(GhCommand::List) => list_remote_gists(run_settings, token_file),
+            Some(GhCommand::Diff) => diff_remote(run_settings, token_file),
+            Some(GhCommand::Login) => login(run_settings),
+            Some(GhCommand::Logout) => logout(),
+            Some(GhCommand::Status) => login_status(run_settings, token_file),
+            _ => Ok(()),
         },
-        _ => {}
+        _ => Ok(()),
     }
 }
@@ -1,20 +1,42 @@
-use std::collections::HashMap;
-use std::convert::Into;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{Display, Formatter};
 use std::path::PathBuf;
 
-use chrono::{DateTime, Local};
-use scan_fmt::scan_fmt;
+use chrono::{DateTime, Local, NaiveDate, Timelike};
 use serde::{Deserialize, Serialize};
 
-use crate::internal::get_scribr_home_dir;
+use crate::error::{ScribrError, ScribrResult};
+use crate::internal::{fetch_user_confirm, get_scribr_home_dir, read_user_line};
 
 pub const SCRIBR_CONFIG_FILE_NAME: &str = "scribr_config.yaml";
 pub const SCRIBR_DEFAULT_NOTEBOOK_FILE_NAME: &str = "notes.txt";
+pub const SCRIBR_GH_TOKEN_FILE_NAME: &str = ".gh_token";
+/// Note attribute holding when the note was last edited, as rfc3339.
+pub const NOTE_EDITED_ATTR: &str = "edited";
+/// Note attribute flagging the note as a todo.
+pub const NOTE_TODO_ATTR: &str = "todo";
+/// Note attribute holding when a todo was done, as rfc3339.
+pub const NOTE_DONE_ATTR: &str = "done";
+/// Note attribute holding the day a todo is due, as `YYYY-MM-DD`.
+pub const NOTE_DUE_ATTR: &str = "due";
+pub const NOTE_DUE_FORMAT: &str = "%Y-%m-%d";
+/// Note attribute flagging a note written by `scribr start`.
+pub const NOTE_START_ATTR: &str = "start";
+/// Note attribute on a note written by `scribr stop`, holding the id of the start note.
+pub const NOTE_STOP_ATTR: &str = "stop";
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Default)]
 pub struct RemoteSettings {
     pub(crate) gist_id: Option<String>,
+    /// File holding a personal access token, for machines that can't run the device flow.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) token_file: Option<String>,
+    /// REST API root, eg `https://github.example.com/api/v3` for GitHub Enterprise Server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) api_url: Option<String>,
+    /// Root that serves the `/login/...` device flow endpoints.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) oauth_url: Option<String>,
 }
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct Settings {
@@ -34,7 +56,7 @@ impl Settings {
             println!("Running in verbose level {}.", verbosity);
             println!("Using note file {}", self.default_notebook);
             if self.no_magic_commands {
-                println!("Ignoring magic all.");
+                println!("Ignoring magic commands.");
             }
         }
         if verbosity > 1 {
@@ -44,15 +66,21 @@ impl Settings {
 
     pub(crate) fn get_default_notebook_path(&self) -> PathBuf {
         let home_dir = get_scribr_home_dir();
-        return home_dir.join(&self.default_notebook);
+        home_dir.join(&self.default_notebook)
+    }
+
+    pub(crate) fn remote_gist_id(&self) -> ScribrResult<String> {
+        self.remote
+            .as_ref()
+            .and_then(|remote| remote.gist_id.clone())
+            .ok_or(ScribrError::RemoteNotConfigured)
     }
 
-    pub(crate) fn new_with_gist_id(gist_id: &str) -> Settings {
-        let mut default_settings = Settings::default();
-        default_settings.remote = Some(RemoteSettings {
-            gist_id: Some(gist_id.to_string()),
-        });
-        default_settings
+    pub(crate) fn new_with_remote(remote: RemoteSettings) -> Settings {
+        Settings {
+            remote: Some(remote),
+            ..Settings::default()
+        }
     }
 }
 
@@ -67,40 +95,408 @@ impl Default for Settings {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Note {
-    timestamp: DateTime<Local>,
+    /// Short id that stays with the note, see `note_id`.
+    pub(crate) id: String,
+    pub(crate) timestamp: DateTime<Local>,
     pub(crate) note_value: String,
+    /// Stored after the id as `key=value`, or just `key` for flags. Neither may hold whitespace.
+    pub(crate) attrs: BTreeMap<String, String>,
+    /// `#tags` in the note text, lower cased and without the `#`, in the order they first appear.
+    pub(crate) tags: Vec<String>,
+    /// `@mentions` in the note text, kept the same way as the tags.
+    pub(crate) mentions: Vec<String>,
+    /// What is inside each `[[link]]` in the note text, either a note id or a note title.
+    pub(crate) links: Vec<String>,
+}
+
+/// Finds the `[[targets]]` of the links in a note, in order and without repeats.
+fn extract_links(note_value: &str) -> Vec<String> {
+    let mut links: Vec<String> = Vec::new();
+    let mut rest = note_value;
+    while let Some(start) = rest.find("[[") {
+        rest = &rest[start + 2..];
+        let end = match rest.find("]]") {
+            Some(end) => end,
+            None => break,
+        };
+        let target = rest[..end].trim();
+        if !target.is_empty() && !target.contains('\n') && !links.iter().any(|l| l == target) {
+            links.push(target.to_string());
+        }
+        rest = &rest[end + 2..];
+    }
+    links
+}
+
+/// Finds words in a note that start with `sigil`, like `#deploy`. The sigil must start the note
+/// or follow whitespace or an opening bracket, and the word must have a letter in it so issue
+/// numbers like `#123` are left out.
+fn extract_sigil_words(note_value: &str, sigil: char) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut previous = None;
+    let mut chars = note_value.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let at_word_start = previous.is_none_or(|p: char| p.is_whitespace() || "([{".contains(p));
+        previous = Some(c);
+        if c != sigil || !at_word_start {
+            continue;
+        }
+        let start = i + c.len_utf8();
+        let mut end = start;
+        while let Some((j, next)) = chars.peek() {
+            if !(next.is_alphanumeric() || "_-/.".contains(*next)) {
+                break;
+            }
+            end = j + next.len_utf8();
+            previous = Some(*next);
+            chars.next();
+        }
+        let word = note_value[start..end]
+            .trim_end_matches(['-', '/', '.'])
+            .to_lowercase();
+        if word.chars().any(char::is_alphabetic) && !words.contains(&word) {
+            words.push(word);
+        }
+    }
+    words
+}
+
+/// Tag as it is stored, so `#Deploy` and `deploy` both match a note tagged `#deploy`.
+pub(crate) fn normalise_tag(tag: &str) -> String {
+    tag.trim_start_matches('#').to_lowercase()
+}
+
+/// Name as it is stored, so `@Alice` and `alice` both match a note mentioning `@alice`.
+pub(crate) fn normalise_mention(name: &str) -> String {
+    name.trim_start_matches('@').to_lowercase()
+}
+
+/// Id for a note from its timestamp and text. Notes written before ids were stored get the same
+/// id every time they are read, so this must never change. It's FNV-1a rather than a std hasher
+/// as those are free to change between rust releases.
+fn note_id(timestamp: &DateTime<Local>, note_value: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in format!("{}|{}", timestamp.timestamp(), note_value).bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:08x}", (hash ^ (hash >> 32)) as u32)
 }
 
 impl Note {
     pub(crate) fn new(note_value: &str) -> Note {
-        return Note {
-            timestamp: Local::now(),
+        Note::new_at(Local::now(), note_value)
+    }
+
+    /// The timestamp is cut to the second, as that's all that is stored.
+    fn new_at(timestamp: DateTime<Local>, note_value: &str) -> Note {
+        let timestamp = timestamp.with_nanosecond(0).unwrap_or(timestamp);
+        Note {
+            id: note_id(&timestamp, note_value),
+            timestamp,
             note_value: note_value.to_string(),
-        };
+            attrs: BTreeMap::new(),
+            tags: extract_sigil_words(note_value, '#'),
+            mentions: extract_sigil_words(note_value, '@'),
+            links: extract_links(note_value),
+        }
+    }
+
+    fn attr_time(&self, attr: &str) -> Option<DateTime<Local>> {
+        let time = self.attrs.get(attr)?;
+        DateTime::parse_from_rfc3339(time)
+            .ok()
+            .map(|time| time.with_timezone(&Local))
+    }
+
+    fn set_attr_now(&mut self, attr: &str) {
+        let now = Local::now().with_nanosecond(0).unwrap();
+        self.attrs.insert(attr.to_string(), now.to_rfc3339());
+    }
+
+    pub(crate) fn edited_at(&self) -> Option<DateTime<Local>> {
+        self.attr_time(NOTE_EDITED_ATTR)
+    }
+
+    /// Changes the text of the note, and everything read from it, keeping its id.
+    pub(crate) fn set_note_value(&mut self, note_value: &str) {
+        self.note_value = note_value.to_string();
+        self.tags = extract_sigil_words(note_value, '#');
+        self.mentions = extract_sigil_words(note_value, '@');
+        self.links = extract_links(note_value);
+    }
+
+    /// Copy of the note with new text, keeping its id and timestamp and marking when it changed.
+    pub(crate) fn edited(&self, note_value: &str) -> Note {
+        let mut edited = self.clone();
+        edited.set_note_value(note_value);
+        edited.set_attr_now(NOTE_EDITED_ATTR);
+        edited
+    }
+
+    pub(crate) fn is_todo(&self) -> bool {
+        self.attrs.contains_key(NOTE_TODO_ATTR)
+    }
+
+    pub(crate) fn mark_todo(&mut self) {
+        self.attrs.insert(NOTE_TODO_ATTR.to_string(), String::new());
+    }
+
+    pub(crate) fn done_at(&self) -> Option<DateTime<Local>> {
+        self.attr_time(NOTE_DONE_ATTR)
+    }
+
+    /// Whether `other` is a copy of this note, maybe edited or done since. Ids are short enough that
+    /// two notes can share one, so the time the note was taken has to match too.
+    pub(crate) fn is_copy_of(&self, other: &Note) -> bool {
+        self.id == other.id && self.timestamp == other.timestamp
+    }
+
+    /// When the note last changed, the later of it being edited or done, or else taken.
+    pub(crate) fn changed_at(&self) -> DateTime<Local> {
+        [self.edited_at(), self.done_at()]
+            .iter()
+            .flatten()
+            .fold(self.timestamp, |latest, time| latest.max(*time))
+    }
+
+    /// A todo that hasn't been done yet.
+    pub(crate) fn is_open_todo(&self) -> bool {
+        self.is_todo() && !self.attrs.contains_key(NOTE_DONE_ATTR)
+    }
+
+    pub(crate) fn due(&self) -> Option<NaiveDate> {
+        let due = self.attrs.get(NOTE_DUE_ATTR)?;
+        NaiveDate::parse_from_str(due, NOTE_DUE_FORMAT).ok()
+    }
+
+    /// Gives the note a due date, which makes it a todo.
+    pub(crate) fn set_due(&mut self, due: NaiveDate) {
+        self.mark_todo();
+        self.attrs.insert(
+            NOTE_DUE_ATTR.to_string(),
+            due.format(NOTE_DUE_FORMAT).to_string(),
+        );
+    }
+
+    pub(crate) fn is_timer_start(&self) -> bool {
+        self.attrs.contains_key(NOTE_START_ATTR)
+    }
+
+    /// Id of the start note this note stops, if it's a stop note.
+    pub(crate) fn stops(&self) -> Option<&str> {
+        self.attrs.get(NOTE_STOP_ATTR).map(String::as_str)
+    }
+
+    /// A note starting a timer on what it says.
+    pub(crate) fn new_timer_start(note_value: &str) -> Note {
+        let mut start = Note::new(note_value);
+        start
+            .attrs
+            .insert(NOTE_START_ATTR.to_string(), String::new());
+        start
+    }
+
+    /// A note stopping the timer `start` started, linking back to it.
+    pub(crate) fn new_timer_stop(start: &Note, took: &str) -> Note {
+        let mut stop = Note::new(&format!("stopped [[{}]] after {}", start.id, took));
+        stop.attrs
+            .insert(NOTE_STOP_ATTR.to_string(), start.id.clone());
+        stop
     }
 
-    pub(crate) fn new_from_line(line: &str) -> Note {
-        // This must match the fmt below, as the parse may fail.
-        let fmt_res = scan_fmt!(line, "{} - {}", String, String);
+    /// Copy of the todo marked as done now.
+    pub(crate) fn done(&self) -> Note {
+        let mut done = self.clone();
+        done.mark_todo();
+        done.set_attr_now(NOTE_DONE_ATTR);
+        done
+    }
 
-        // todo: make this destructuring more solid
-        let (timestamp_str, note_value) =
-            fmt_res.expect("Format of notes should be as \"{timestamp} - {note}\"");
-        let timestamp = DateTime::parse_from_rfc2822(&*timestamp_str)
-            .expect("All Datetime should be in the rfc2822 format")
+    /// Loops round prompting the user for a note until they're happy with one. Returns `None` if
+    /// stdin closes before then.
+    pub(crate) fn from_prompt() -> ScribrResult<Option<Note>> {
+        loop {
+            let timestamp = Local::now();
+            print!(
+                "{} | what did you do today? ",
+                timestamp.format("%a %d %b %Y")
+            );
+            let note_value = match read_user_line()? {
+                Some(note_value) => note_value,
+                None => return Ok(None),
+            };
+            if note_value.trim().is_empty() {
+                continue;
+            }
+            if fetch_user_confirm("Persist this?")? {
+                return Ok(Some(Note::new_at(timestamp, &note_value)));
+            }
+        }
+    }
+
+    pub(crate) fn new_from_line(line: &str) -> ScribrResult<Note> {
+        // This must match the fmt below, as the parse may fail. The rfc2822 timestamp has spaces
+        // so split on the first separator rather than the first whitespace.
+        let (header, note_value) = line.split_once(" - ").ok_or_else(|| {
+            ScribrError::Parse(format!(
+                "note \"{}\", it should be \"{{timestamp}} [{{id}}] - {{note}}\"",
+                line
+            ))
+        })?;
+        // older notes have no id
+        let (timestamp_str, id, attrs) = match header.split_once(" [") {
+            Some((timestamp_str, bracket)) => {
+                let mut tokens = bracket
+                    .strip_suffix(']')
+                    .ok_or_else(|| ScribrError::Parse(format!("note id \"[{}\"", bracket)))?
+                    .split_whitespace();
+                let id = tokens
+                    .next()
+                    .ok_or_else(|| ScribrError::Parse("note id \"[]\"".to_string()))?;
+                let attrs = tokens
+                    .map(|token| match token.split_once('=') {
+                        Some((key, value)) => (key.to_string(), value.to_string()),
+                        None => (token.to_string(), String::new()),
+                    })
+                    .collect();
+                (timestamp_str, Some(id), attrs)
+            }
+            None => (header, None, BTreeMap::new()),
+        };
+        let timestamp = DateTime::parse_from_rfc2822(timestamp_str)
+            .map_err(|e| {
+                ScribrError::Parse(format!(
+                    "timestamp \"{}\", it should be rfc2822 ({})",
+                    timestamp_str, e
+                ))
+            })?
             .with_timezone(&Local);
+        // only lines with an id were written escaped, older ones keep their backslashes as typed
+        let (id, note_value) = match id {
+            Some(id) => (id.to_string(), unescape_note_value(note_value)),
+            None => (note_id(&timestamp, note_value), note_value.to_string()),
+        };
 
-        return Note {
+        Ok(Note {
+            id,
             timestamp,
+            tags: extract_sigil_words(&note_value, '#'),
+            mentions: extract_sigil_words(&note_value, '@'),
+            links: extract_links(&note_value),
             note_value,
+            attrs,
+        })
+    }
+
+    /// The first line of the note, which `[[links]]` can use instead of the id.
+    pub(crate) fn title(&self) -> &str {
+        self.note_value.lines().next().unwrap_or_default().trim()
+    }
+
+    /// Whether the note has all of `tags`, or any of them when `any` is set. No tags always match.
+    pub(crate) fn has_tags(&self, tags: &[String], any: &bool) -> bool {
+        if tags.is_empty() {
+            return true;
+        }
+        let mut wanted = tags.iter().map(|tag| normalise_tag(tag));
+        if *any {
+            wanted.any(|tag| self.tags.contains(&tag))
+        } else {
+            wanted.all(|tag| self.tags.contains(&tag))
+        }
+    }
+
+    /// The timestamp, id and attributes that go before the note text.
+    fn header(&self) -> String {
+        let mut header = format!("{} [{}", self.timestamp.to_rfc2822(), self.id);
+        for (key, value) in &self.attrs {
+            header.push(' ');
+            header.push_str(key);
+            if !value.is_empty() {
+                header.push('=');
+                header.push_str(value);
+            }
+        }
+        header.push(']');
+        header
+    }
+
+    /// Formats the note for the console, with any extra lines of the note under the first and
+    /// todos marked `[ ]` or `[x]` along with any due date.
+    pub(crate) fn to_indented_string(&self, indent: &str) -> String {
+        let note_value = self
+            .note_value
+            .lines()
+            .collect::<Vec<&str>>()
+            .join(&format!("\n{}", indent));
+        let state = match (self.is_todo(), self.is_open_todo()) {
+            (true, true) => "[ ] ",
+            (true, false) => "[x] ",
+            _ => "",
         };
+        let due = match self.due() {
+            Some(due) => format!("(due {}) ", due.format(NOTE_DUE_FORMAT)),
+            None => String::new(),
+        };
+        format!(
+            "{} [{}] - {}{}{}",
+            self.timestamp.to_rfc2822(),
+            self.id,
+            state,
+            due,
+            note_value
+        )
+    }
+}
+
+/// Notes are stored one per line, so newlines and backslashes in the note are escaped.
+fn escape_note_value(note_value: &str) -> String {
+    let mut escaped = String::with_capacity(note_value.len());
+    for c in note_value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape_note_value(note_value: &str) -> String {
+    let mut unescaped = String::with_capacity(note_value.len());
+    let mut chars = note_value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('\\') => unescaped.push('\\'),
+            // not an escape we wrote, keep it as it is
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
     }
+    unescaped
 }
 
 impl Display for Note {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} - {}", self.timestamp.to_rfc2822(), self.note_value)
+        write!(
+            f,
+            "{} - {}",
+            self.header(),
+            escape_note_value(&self.note_value)
+        )
     }
 }
 
@@ -133,6 +529,11 @@ pub struct GhAccessResponse {
     scope: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct GhUserResponse {
+    pub login: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Owner {
     pub login: String,
@@ -161,9 +562,14 @@ pub struct FileData {
     pub filename: String,
     #[serde(rename = "type")]
     pub r#type: String,
-    pub language: String,
+    pub language: Option<String>,
     pub raw_url: String,
     pub size: i64,
+    /// Only sent when fetching a single gist, and cut short for files over a megabyte.
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default)]
+    pub truncated: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -191,12 +597,35 @@ pub struct GhGistResponse {
     pub public: bool,
     pub created_at: String,
     pub updated_at: String,
-    pub description: String,
+    /// Null for gists made without one, which can turn up when listing all the user's gists.
+    #[serde(default)]
+    pub description: Option<String>,
     pub comments: i64,
     pub user: Option<String>,
     pub comments_url: String,
     pub owner: Owner,
     pub truncated: bool,
+    /// Only sent when fetching a single gist, newest revision first.
+    #[serde(default)]
+    pub history: Vec<GhGistHistory>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GhGistHistory {
+    pub version: String,
+    pub committed_at: String,
+    pub change_status: GhChangeStatus,
+    pub url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct GhChangeStatus {
+    #[serde(default)]
+    pub total: i64,
+    #[serde(default)]
+    pub additions: i64,
+    #[serde(default)]
+    pub deletions: i64,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -208,3 +637,171 @@ pub struct GhGistCreateRequest {
 
 pub type GhFiles = HashMap<String, File>;
 pub type GhFilesData = HashMap<String, FileData>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_note_from_line_round_trip() {
+        let line = "Wed, 1 Mar 2023 10:00:00 +0000 - a note - with a dash";
+        let note = Note::new_from_line(line).unwrap();
+        assert_eq!(note.note_value, "a note - with a dash");
+        assert_eq!(Note::new_from_line(&note.to_string()).unwrap(), note);
+    }
+
+    #[test]
+    fn test_note_ids() {
+        let legacy_line = "Wed, 1 Mar 2023 10:00:00 +0000 - an old note";
+        let legacy_note = Note::new_from_line(legacy_line).unwrap();
+        assert_eq!(legacy_note.id.len(), 8);
+        assert_eq!(Note::new_from_line(legacy_line).unwrap().id, legacy_note.id);
+
+        // writing an old note back out keeps the id it was read with
+        let upgraded_line = legacy_note.to_string();
+        assert!(upgraded_line.contains(&format!(" [{}] - ", legacy_note.id)));
+        assert_eq!(Note::new_from_line(&upgraded_line).unwrap(), legacy_note);
+
+        let line = "Wed, 1 Mar 2023 10:00:00 +0000 [abc123 edited=2023-03-02T09:00:00+00:00 todo] - edited";
+        let note = Note::new_from_line(line).unwrap();
+        assert_eq!(note.id, "abc123");
+        assert_eq!(note.attrs.get("todo").map(String::as_str), Some(""));
+        assert_eq!(
+            note.edited_at().unwrap().to_rfc3339(),
+            DateTime::parse_from_rfc3339("2023-03-02T09:00:00+00:00")
+                .unwrap()
+                .with_timezone(&Local)
+                .to_rfc3339()
+        );
+        assert_eq!(Note::new_from_line(&note.to_string()).unwrap(), note);
+        assert!(matches!(
+            Note::new_from_line("Wed, 1 Mar 2023 10:00:00 +0000 [] - no id"),
+            Err(ScribrError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_note_tags() {
+        let note = Note::new("#Deploy of api (#incident-42) fixed #123, see a#b and #deploy/");
+        assert_eq!(note.tags, vec!["deploy", "incident-42"]);
+        assert!(note.has_tags(&[], &false));
+        assert!(note.has_tags(&["#deploy".to_string(), "Incident-42".to_string()], &false));
+        assert!(!note.has_tags(&["deploy".to_string(), "oncall".to_string()], &false));
+        assert!(note.has_tags(&["deploy".to_string(), "oncall".to_string()], &true));
+        assert_eq!(
+            Note::new_from_line(&note.to_string()).unwrap().tags,
+            note.tags
+        );
+    }
+
+    #[test]
+    fn test_note_mentions() {
+        let note = Note::new(
+            "paired with @Alice and @bob.smith on the flaky test, cc alice@example.com @alice",
+        );
+        assert_eq!(note.mentions, vec!["alice", "bob.smith"]);
+        assert_eq!(normalise_mention("@Alice"), "alice");
+    }
+
+    #[test]
+    fn test_note_links() {
+        let note = Note::new(
+            "Fix for [[ab12cd34]], see [[ Deploy runbook ]] and [[ab12cd34]] [[]] [[open",
+        );
+        assert_eq!(note.links, vec!["ab12cd34", "Deploy runbook"]);
+        assert_eq!(
+            Note::new("Deploy runbook\nstep one").title(),
+            "Deploy runbook"
+        );
+    }
+
+    #[test]
+    fn test_todo_lifecycle() {
+        let mut note = Note::new("ship the release");
+        assert!(!note.is_todo());
+        note.mark_todo();
+        assert!(note.is_open_todo());
+        assert!(note
+            .to_indented_string("")
+            .ends_with("- [ ] ship the release"));
+
+        let done = Note::new_from_line(&note.done().to_string()).unwrap();
+        assert!(done.is_todo() && !done.is_open_todo());
+        assert!(done.done_at().is_some());
+        assert_eq!(done.id, note.id);
+        assert!(done
+            .to_indented_string("")
+            .ends_with("- [x] ship the release"));
+    }
+
+    #[test]
+    fn test_note_due() {
+        let mut note = Note::new("renew the cert");
+        assert_eq!(note.due(), None);
+        note.set_due(NaiveDate::from_ymd_opt(2026, 11, 2).unwrap());
+        assert!(note.is_open_todo());
+
+        let note = Note::new_from_line(&note.to_string()).unwrap();
+        assert_eq!(note.due(), NaiveDate::from_ymd_opt(2026, 11, 2));
+        assert!(note
+            .to_indented_string("")
+            .ends_with("- [ ] (due 2026-11-02) renew the cert"));
+    }
+
+    #[test]
+    fn test_multi_line_note_round_trip() {
+        let note = Note::new("panicked at src/main.rs:1\n  at C:\\notes\\new\r\n");
+        let line = note.to_string();
+        assert!(!line.contains('\n'));
+        assert_eq!(
+            Note::new_from_line(&line).unwrap().note_value,
+            note.note_value
+        );
+        assert_eq!(
+            Note::new_from_line("Wed, 1 Mar 2023 10:00:00 +0000 - a \\d+ regex")
+                .unwrap()
+                .note_value,
+            "a \\d+ regex"
+        );
+    }
+
+    #[test]
+    fn test_legacy_line_keeps_backslashes() {
+        let legacy_line = "Wed, 1 Mar 2023 10:00:00 +0000 - moved to C:\\new\\notes, matched \\\\d";
+        let legacy_note = Note::new_from_line(legacy_line).unwrap();
+        assert_eq!(
+            legacy_note.note_value,
+            "moved to C:\\new\\notes, matched \\\\d"
+        );
+
+        // writing it back out escapes it under an id, and it reads back the same
+        let upgraded_note = Note::new_from_line(&legacy_note.to_string()).unwrap();
+        assert_eq!(upgraded_note, legacy_note);
+    }
+
+    #[test]
+    fn test_note_from_bad_line() {
+        assert!(matches!(
+            Note::new_from_line("<<<<<<< HEAD"),
+            Err(ScribrError::Parse(_))
+        ));
+        assert!(matches!(
+            Note::new_from_line("yesterday - a note"),
+            Err(ScribrError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_gist_without_description() {
+        let gist = std::fs::read_to_string(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/gh_gist.json"),
+        )
+        .unwrap();
+        let described: GhGistResponse = serde_yaml::from_str(&gist).unwrap();
+        assert!(described.description.is_some());
+
+        let gist = gist.replace("\"Gist for storing my scribr notes\"", "null");
+        let undescribed: GhGistResponse = serde_yaml::from_str(&gist).unwrap();
+        assert_eq!(undescribed.description, None);
+    }
+}